@@ -60,6 +60,8 @@ Options:
           Don't update any existing default toolchain after install
       --no-modify-path
           Don't configure the PATH environment variable
+      --dry-run
+          Print what would be installed without making any changes
   -h, --help
           Print help
   -V, --version
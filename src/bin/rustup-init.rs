@@ -15,23 +15,10 @@
 
 use std::process::ExitCode;
 
-use anyhow::{anyhow, Context, Result};
-use cfg_if::cfg_if;
-// Public macros require availability of the internal symbols
-use rs_tracing::{
-    close_trace_file, close_trace_file_internal, open_trace_file, trace_to_file_internal,
-};
+use anyhow::Result;
 
-use rustup::cli::common;
-use rustup::cli::proxy_mode;
-use rustup::cli::rustup_mode;
-#[cfg(windows)]
-use rustup::cli::self_update;
-use rustup::cli::setup_mode;
+use rustup::cli::{self, common};
 use rustup::currentprocess::Process;
-use rustup::env_var::RUST_RECURSION_COUNT_MAX;
-use rustup::errors::RustupError;
-use rustup::is_proxyable_tools;
 use rustup::utils::utils;
 
 #[tokio::main]
@@ -46,7 +33,7 @@ async fn main() -> Result<ExitCode> {
     );
     let subscriber = rustup::cli::log::tracing_subscriber(&process);
     tracing::subscriber::set_global_default(subscriber)?;
-    let result = run_rustup(&process).await;
+    let result = cli::run_rustup(&process).await;
     // We're tracing, so block until all spans are exported.
     #[cfg(feature = "otel")]
     opentelemetry::global::shutdown_tracer_provider();
@@ -60,86 +47,26 @@ async fn main() -> Result<ExitCode> {
     }
 }
 
-#[cfg_attr(feature = "otel", tracing::instrument)]
-async fn run_rustup(process: &Process) -> Result<utils::ExitCode> {
-    if let Ok(dir) = process.var("RUSTUP_TRACE_DIR") {
-        open_trace_file!(dir)?;
-    }
-    let result = run_rustup_inner(process).await;
-    if process.var("RUSTUP_TRACE_DIR").is_ok() {
-        close_trace_file!();
-    }
-    result
-}
-
-#[cfg_attr(feature = "otel", tracing::instrument(err))]
-async fn run_rustup_inner(process: &Process) -> Result<utils::ExitCode> {
-    // Guard against infinite proxy recursion. This mostly happens due to
-    // bugs in rustup.
-    do_recursion_guard(process)?;
-
-    // Before we do anything else, ensure we know where we are and who we
-    // are because otherwise we cannot proceed usefully.
-    let current_dir = process
-        .current_dir()
-        .context(RustupError::LocatingWorkingDir)?;
-    utils::current_exe()?;
-
-    match process.name().as_deref() {
-        Some("rustup") => rustup_mode::main(current_dir, process).await,
-        Some(n) if n.starts_with("rustup-setup") || n.starts_with("rustup-init") => {
-            // NB: The above check is only for the prefix of the file
-            // name. Browsers rename duplicates to
-            // e.g. rustup-setup(2), and this allows all variations
-            // to work.
-            setup_mode::main(current_dir, process).await
-        }
-        Some(n) if n.starts_with("rustup-gc-") => {
-            // This is the final uninstallation stage on windows where
-            // rustup deletes its own exe
-            cfg_if! {
-                if #[cfg(windows)] {
-                    self_update::complete_windows_uninstall(process)
-                } else {
-                    unreachable!("Attempted to use Windows-specific code on a non-Windows platform. Aborting.")
-                }
-            }
-        }
-        Some(n) => {
-            is_proxyable_tools(n)?;
-            proxy_mode::main(n, current_dir, process)
-                .await
-                .map(utils::ExitCode::from)
-        }
-        None => {
-            // Weird case. No arg0, or it's unparsable.
-            Err(rustup::cli::errors::CLIError::NoExeName.into())
-        }
-    }
-}
-
-fn do_recursion_guard(process: &Process) -> Result<()> {
-    let recursion_count = process
-        .var("RUST_RECURSION_COUNT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    if recursion_count > RUST_RECURSION_COUNT_MAX {
-        return Err(anyhow!("infinite recursion detected"));
-    }
-
-    Ok(())
-}
-
 /// Windows pre-main security mitigations.
 ///
 /// This attempts to defend against malicious DLLs that may sit alongside
 /// rustup-init in the user's download folder.
+///
+/// Setting `RUSTUP_DISABLE_DLL_MITIGATION` skips this, which is meant for test
+/// harnesses and sandboxes where the call misbehaves: with it set, a DLL
+/// planted next to the binary may get loaded instead of the system one.
+/// The variable is read from the real environment since this runs before the
+/// [`Process`] is set up.
+///
+/// Returns whether the mitigation was applied.
 #[cfg(windows)]
-pub fn pre_rustup_main_init() {
+pub fn pre_rustup_main_init() -> bool {
     use windows_sys::Win32::System::LibraryLoader::{
         SetDefaultDllDirectories, LOAD_LIBRARY_SEARCH_SYSTEM32,
     };
+    if std::env::var_os("RUSTUP_DISABLE_DLL_MITIGATION").is_some() {
+        return false;
+    }
     // Default to loading delay loaded DLLs from the system directory.
     // For DLLs loaded at load time, this relies on the `delayload` linker flag.
     // This is only necessary prior to Windows 10 RS1. See build.rs for details.
@@ -149,4 +76,18 @@ pub fn pre_rustup_main_init() {
         // But just to be safe and to catch mistakes, assert that it succeeded.
         assert_ne!(result, 0);
     }
+    true
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dll_mitigation_can_be_disabled() {
+        std::env::set_var("RUSTUP_DISABLE_DLL_MITIGATION", "1");
+        let applied = pre_rustup_main_init();
+        std::env::remove_var("RUSTUP_DISABLE_DLL_MITIGATION");
+        assert!(!applied);
+    }
 }
@@ -12,3 +12,155 @@ pub mod rustup_mode;
 pub mod self_update;
 pub mod setup_mode;
 mod topical_doc;
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cfg_if::cfg_if;
+use tracing::warn;
+
+use crate::currentprocess::Process;
+use crate::env_var::RecursionGuard;
+use crate::errors::RustupError;
+use crate::is_proxyable_tools;
+use crate::utils::utils;
+use common::InvocationKind;
+
+/// Runs rustup as the binary named by `process`' first argument would, be it
+/// `rustup`, `rustup-init` or a proxy.
+///
+/// This doesn't set up tracing: the `rustup` binary installs
+/// [`log::tracing_subscriber`] as the global default first, while programs
+/// embedding rustup can keep the subscriber they already have.
+#[cfg_attr(feature = "otel", tracing::instrument)]
+pub async fn run_rustup(process: &Process) -> Result<utils::ExitCode> {
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        tracing::Span::current().set_parent(log::parent_context(process));
+    }
+    if let Some(path) = process.var_os("RUSTUP_LOG_FILE") {
+        let path = Path::new(&path);
+        match fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => process.set_log_file(file),
+            Err(e) => warn!("could not open log file '{}': {e}", path.display()),
+        }
+    }
+    if let Ok(dir) = process.var("RUSTUP_TRACE_DIR") {
+        open_trace_file!(dir)?;
+    }
+    let result = run_rustup_inner(process).await;
+    if process.var("RUSTUP_TRACE_DIR").is_ok() {
+        close_trace_file!();
+    }
+    result
+}
+
+#[cfg_attr(feature = "otel", tracing::instrument(err))]
+async fn run_rustup_inner(process: &Process) -> Result<utils::ExitCode> {
+    // Guard against infinite proxy recursion. This mostly happens due to
+    // bugs in rustup.
+    do_recursion_guard(process)?;
+
+    // Before we do anything else, ensure we know where we are and who we
+    // are because otherwise we cannot proceed usefully.
+    let current_dir = process
+        .current_dir()
+        .context(RustupError::LocatingWorkingDir)?;
+    utils::current_exe()?;
+
+    match common::invocation_kind(process)? {
+        InvocationKind::Rustup => rustup_mode::main(current_dir, process).await,
+        InvocationKind::Setup => setup_mode::main(current_dir, process).await,
+        InvocationKind::Gc => {
+            // This is the final uninstallation stage on windows where
+            // rustup deletes its own exe
+            cfg_if! {
+                if #[cfg(windows)] {
+                    self_update::complete_windows_uninstall(process)
+                } else {
+                    unreachable!("Attempted to use Windows-specific code on a non-Windows platform. Aborting.")
+                }
+            }
+        }
+        InvocationKind::Proxy(n) => {
+            is_proxyable_tools(&n)?;
+            proxy_mode::main(&n, current_dir, process)
+                .await
+                .map(utils::ExitCode::from)
+        }
+    }
+}
+
+fn do_recursion_guard(process: &Process) -> Result<()> {
+    RecursionGuard::from_process(process).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::currentprocess::TestProcess;
+    use crate::utils::notify::NotificationLevel;
+
+    #[tokio::test]
+    async fn run_rustup_uses_existing_subscriber() {
+        let home = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let vars = HashMap::from([
+            (
+                "RUSTUP_HOME".to_owned(),
+                home.path().join("rustup").display().to_string(),
+            ),
+            (
+                "CARGO_HOME".to_owned(),
+                home.path().join("cargo").display().to_string(),
+            ),
+        ]);
+        // The test process installs its own thread-local subscriber.
+        let tp = TestProcess::new(home.path(), &["rustup", "--version"], vars, "");
+
+        let code = run_rustup(&tp.process).await.unwrap();
+        assert_eq!(code.0, 0);
+        assert!(tp.captured_logs().contains(&(
+            NotificationLevel::Info,
+            "This is the version for the rustup toolchain manager, not the rustc compiler."
+                .to_owned()
+        )));
+
+        // No global default was set along the way: once the subscriber of the
+        // test process is gone, events go nowhere.
+        drop(tp);
+        assert!(tracing::dispatcher::get_default(|d| {
+            d.is::<tracing::subscriber::NoSubscriber>()
+        }));
+    }
+
+    #[tokio::test]
+    async fn run_rustup_warns_about_unopenable_log_file() {
+        let home = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let log_file = home.path().join("missing/rustup.log");
+        let vars = HashMap::from([
+            (
+                "RUSTUP_HOME".to_owned(),
+                home.path().join("rustup").display().to_string(),
+            ),
+            (
+                "CARGO_HOME".to_owned(),
+                home.path().join("cargo").display().to_string(),
+            ),
+            ("RUSTUP_LOG_FILE".to_owned(), log_file.display().to_string()),
+        ]);
+        let tp = TestProcess::new(home.path(), &["rustup", "--version"], vars, "");
+
+        let code = run_rustup(&tp.process).await.unwrap();
+        assert_eq!(code.0, 0);
+        let prefix = format!("could not open log file '{}': ", log_file.display());
+        assert!(tp
+            .captured_logs()
+            .iter()
+            .any(|(level, msg)| *level == NotificationLevel::Warn && msg.starts_with(&prefix)));
+    }
+}
@@ -15,6 +15,7 @@ use tracing::{debug, error, info, trace, warn};
 
 use super::self_update;
 use crate::cli::download_tracker::DownloadTracker;
+use crate::cli::errors::CLIError;
 use crate::currentprocess::{terminalsource, Process};
 use crate::dist::{
     manifest::ComponentStatus, notifications as dist_notifications, TargetTriple, ToolchainDesc,
@@ -26,6 +27,55 @@ use crate::utils::notify::NotificationLevel;
 use crate::utils::utils;
 use crate::{config::Cfg, notifications::Notification};
 
+/// The ways the rustup binary can behave, depending on the name it was
+/// invoked under.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvocationKind {
+    Rustup,
+    Setup,
+    Gc,
+    /// Running as a proxy for the named tool, e.g. `cargo`.
+    Proxy(String),
+}
+
+/// Classifies how the current process was invoked, based on its arg0.
+///
+/// Fails if there is no usable arg0.
+pub fn invocation_kind(process: &Process) -> Result<InvocationKind> {
+    let name = process.name();
+
+    // Allow tests to pick a branch directly, regardless of arg0.
+    #[cfg(feature = "test")]
+    if let Ok(forced) = process.var("RUSTUP_FORCE_TOOL") {
+        return match forced.as_str() {
+            "rustup" => Ok(InvocationKind::Rustup),
+            "setup" => Ok(InvocationKind::Setup),
+            "gc" => Ok(InvocationKind::Gc),
+            "proxy" => name
+                .map(InvocationKind::Proxy)
+                .ok_or_else(|| CLIError::NoExeName.into()),
+            _ => Err(anyhow!("invalid RUSTUP_FORCE_TOOL: '{forced}'")),
+        };
+    }
+
+    match name {
+        Some(n) if n == "rustup" => Ok(InvocationKind::Rustup),
+        Some(n) if n.starts_with("rustup-setup") || n.starts_with("rustup-init") => {
+            // NB: The above check is only for the prefix of the file
+            // name. Browsers rename duplicates to
+            // e.g. rustup-setup(2), and this allows all variations
+            // to work.
+            Ok(InvocationKind::Setup)
+        }
+        Some(n) if n.starts_with("rustup-gc-") => Ok(InvocationKind::Gc),
+        Some(n) => Ok(InvocationKind::Proxy(n)),
+        None => {
+            // Weird case. No arg0, or it's unparsable.
+            Err(CLIError::NoExeName.into())
+        }
+    }
+}
+
 pub(crate) const WARN_COMPLETE_PROFILE: &str = "downloading with complete profile isn't recommended unless you are a developer of the rust language";
 
 pub(crate) fn confirm(question: &str, default: bool, process: &Process) -> Result<bool> {
@@ -125,7 +175,8 @@ pub(crate) fn read_line(process: &Process) -> Result<String> {
 pub(super) struct Notifier {
     tracker: Mutex<DownloadTracker>,
     ram_notice_shown: RefCell<bool>,
-    verbose: bool,
+    /// The least severe notifications that are passed on to the logger.
+    min_level: NotificationLevel,
 }
 
 impl Notifier {
@@ -133,7 +184,11 @@ impl Notifier {
         Self {
             tracker: Mutex::new(DownloadTracker::new_with_display_progress(!quiet, process)),
             ram_notice_shown: RefCell::new(false),
-            verbose,
+            min_level: if verbose {
+                NotificationLevel::Trace
+            } else {
+                NotificationLevel::Info
+            },
         }
     }
 
@@ -153,12 +208,13 @@ impl Notifier {
             }
         };
         let level = n.level();
+        if !level.at_least(self.min_level) {
+            return;
+        }
         for n in format!("{n}").lines() {
             match level {
                 NotificationLevel::Debug => {
-                    if self.verbose {
-                        debug!("{}", n);
-                    }
+                    debug!("{}", n);
                 }
                 NotificationLevel::Info => {
                     info!("{}", n);
@@ -635,3 +691,45 @@ pub(crate) fn warn_if_host_is_emulated(process: &Process) {
         warn!("For best compatibility and performance you should reinstall rustup for your native CPU.");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::currentprocess::TestProcess;
+    use crate::test::Env;
+
+    #[test]
+    fn invocation_kind_from_arg0() {
+        let cases = [
+            ("rustup", InvocationKind::Rustup),
+            ("rustup-init", InvocationKind::Setup),
+            ("rustup-init(2)", InvocationKind::Setup),
+            ("rustup-setup", InvocationKind::Setup),
+            ("rustup-gc-1234", InvocationKind::Gc),
+            ("cargo", InvocationKind::Proxy("cargo".to_owned())),
+            ("/usr/bin/rustc", InvocationKind::Proxy("rustc".to_owned())),
+        ];
+        for (arg0, kind) in cases {
+            let tp = TestProcess::new("", &[arg0], HashMap::new(), "");
+            assert_eq!(invocation_kind(&tp.process).unwrap(), kind, "{arg0}");
+        }
+
+        let tp = TestProcess::new::<_, &str>("", &[], HashMap::new(), "");
+        assert!(invocation_kind(&tp.process).is_err());
+    }
+
+    #[test]
+    fn invocation_kind_forced() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_FORCE_TOOL", "setup");
+        let tp = TestProcess::new("", &["cargo"], vars, "");
+        assert_eq!(invocation_kind(&tp.process).unwrap(), InvocationKind::Setup);
+
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_FORCE_TOOL", "bogus");
+        let tp = TestProcess::new("", &["cargo"], vars, "");
+        assert!(invocation_kind(&tp.process).is_err());
+    }
+}
@@ -1,5 +1,6 @@
-use std::{fmt, io::Write};
+use std::{cell::RefCell, fmt, io::Write};
 
+use chrono::{DateTime, SecondsFormat, Utc};
 use termcolor::{Color, ColorSpec, WriteColor};
 use tracing::{level_filters::LevelFilter, Event, Subscriber};
 use tracing_subscriber::{
@@ -14,21 +15,62 @@ use tracing_subscriber::{
 #[cfg(feature = "otel")]
 use opentelemetry_sdk::trace::Tracer;
 
-use crate::{currentprocess::Process, utils::notify::NotificationLevel};
+use crate::{
+    currentprocess::{Encoding, Process},
+    utils::notify::NotificationLevel,
+};
 
 pub fn tracing_subscriber(process: &Process) -> impl tracing::Subscriber {
     use tracing_subscriber::{layer::SubscriberExt, Registry};
 
+    let registry = Registry::default().with(console_logger(process));
+    #[cfg(feature = "test")]
+    let registry = registry.with(log_capture(process));
     #[cfg(feature = "otel")]
-    let telemetry = telemetry(process);
-    let console_logger = console_logger(process);
-    #[cfg(feature = "otel")]
-    {
-        Registry::default().with(console_logger).with(telemetry)
-    }
-    #[cfg(not(feature = "otel"))]
-    {
-        Registry::default().with(console_logger)
+    let registry = registry.with(telemetry(process));
+    registry
+}
+
+/// A [`Layer`] that records the events the console logger receives into the
+/// in-memory log of a [`TestProcess`][`crate::currentprocess::TestProcess`],
+/// so that tests can assert on them without parsing `stderr`.
+///
+/// Returns `None` for OS processes.
+#[cfg(feature = "test")]
+fn log_capture<S>(process: &Process) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let logs = match process {
+        Process::TestProcess(tp) => tp.logs.clone(),
+        _ => return None,
+    };
+    Some(LogCapture(logs).with_filter(EnvFilter::new("rustup=DEBUG")))
+}
+
+#[cfg(feature = "test")]
+struct LogCapture(crate::currentprocess::CapturedLogs);
+
+#[cfg(feature = "test")]
+impl<S: Subscriber> Layer<S> for LogCapture {
+    fn on_event(&self, event: &Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        struct Message(String);
+
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let level = NotificationLevel::from(*event.metadata().level());
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((level, message.0));
     }
 }
 
@@ -38,6 +80,8 @@ pub fn tracing_subscriber(process: &Process) -> impl tracing::Subscriber {
 /// When the `RUSTUP_LOG` environment variable is present, a standard [`tracing_subscriber`]
 /// formatter will be used according to the filtering directives set in its value.
 /// Otherwise, this logger will use [`EventFormatter`] to mimic "classic" Rustup `stderr` output.
+/// Setting `RUSTUP_LOG_TIME=1` additionally prefixes each of those lines with a timestamp,
+/// and setting `RUSTUP_LOG_ICONS=1` replaces the level names with icons on Unicode terminals.
 fn console_logger<S>(process: &Process) -> impl Layer<S>
 where
     S: Subscriber + for<'span> LookupSpan<'span>,
@@ -50,7 +94,17 @@ where
         _ => process.stderr().is_a_tty(process),
     };
     let maybe_rustup_log_directives = process.var("RUSTUP_LOG");
+    let with_time = process.var_bool("RUSTUP_LOG_TIME");
+    let with_icons =
+        process.var_bool("RUSTUP_LOG_ICONS") && process.output_encoding() == Encoding::Utf8;
+    let palette = process
+        .var("RUSTUP_LOG_COLORS")
+        .map(|s| Palette::parse(&s))
+        .unwrap_or_default();
+    let clock = process.clone();
     let process = process.clone();
+    // `stderr()` is called anew for every event rather than once up front, so
+    // that the logger always writes to the stream the process currently has.
     let logger = tracing_subscriber::fmt::layer()
         .with_writer(move || process.stderr())
         .with_ansi(has_ansi);
@@ -63,7 +117,12 @@ where
         // Receive log lines from Rustup only.
         let env_filter = EnvFilter::new("rustup=DEBUG");
         logger
-            .event_format(EventFormatter)
+            .event_format(EventFormatter {
+                with_time,
+                with_icons,
+                palette,
+                clock,
+            })
             .with_filter(env_filter)
             .boxed()
     }
@@ -71,7 +130,16 @@ where
 
 // Adapted from
 // https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/trait.FormatEvent.html#examples
-struct EventFormatter;
+struct EventFormatter {
+    /// Whether to prefix each line with an RFC 3339 timestamp.
+    with_time: bool,
+    /// Whether to print level icons instead of level names.
+    with_icons: bool,
+    /// The colors of the level prefixes.
+    palette: Palette,
+    /// The process whose clock provides the timestamps.
+    clock: Process,
+}
 
 impl<S, N> FormatEvent<S, N> for EventFormatter
 where
@@ -86,22 +154,55 @@ where
     ) -> fmt::Result {
         let has_ansi = writer.has_ansi_escapes();
         let level = NotificationLevel::from(*event.metadata().level());
-        {
-            let mut buf = termcolor::Buffer::ansi();
+        if self.with_time {
+            let now = DateTime::<Utc>::from(self.clock.now());
+            write!(
+                writer,
+                "{} ",
+                now.to_rfc3339_opts(SecondsFormat::Micros, true)
+            )?;
+        }
+        with_prefix_buf(|buf| {
             if has_ansi {
-                _ = buf.set_color(ColorSpec::new().set_bold(true).set_fg(level.fg_color()));
+                _ = buf.set_color(
+                    ColorSpec::new()
+                        .set_bold(true)
+                        .set_fg(self.palette.color(&level)),
+                );
+            }
+            let prefix = level.prefix(self.with_icons);
+            if self.with_icons {
+                _ = write!(buf, "{prefix} ");
+            } else {
+                _ = write!(buf, "{prefix}: ");
             }
-            _ = write!(buf, "{level}: ");
             if has_ansi {
                 _ = buf.reset();
             }
-            writer.write_str(std::str::from_utf8(buf.as_slice()).unwrap())?;
-        }
+            writer.write_str(std::str::from_utf8(buf.as_slice()).unwrap())
+        })?;
         ctx.field_format().format_fields(writer.by_ref(), event)?;
         writeln!(writer)
     }
 }
 
+thread_local! {
+    /// The buffer [`EventFormatter`] renders level prefixes into.
+    ///
+    /// Each thread keeps its own, so that logging reuses one allocation
+    /// instead of creating a buffer for every event.
+    static PREFIX_BUF: RefCell<termcolor::Buffer> = RefCell::new(termcolor::Buffer::ansi());
+}
+
+/// Runs `f` with this thread's cleared prefix buffer.
+fn with_prefix_buf<R>(f: impl FnOnce(&mut termcolor::Buffer) -> R) -> R {
+    PREFIX_BUF.with(|buf| {
+        let mut buf = buf.borrow_mut();
+        buf.clear();
+        f(&mut buf)
+    })
+}
+
 impl NotificationLevel {
     fn fg_color(&self) -> Option<Color> {
         match self {
@@ -114,6 +215,70 @@ impl NotificationLevel {
     }
 }
 
+/// The colors of the level prefixes printed by [`EventFormatter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Palette {
+    trace: Option<Color>,
+    debug: Option<Color>,
+    info: Option<Color>,
+    warn: Option<Color>,
+    error: Option<Color>,
+}
+
+impl Palette {
+    /// Parses a `RUSTUP_LOG_COLORS` theme such as `warn=cyan,error=magenta`.
+    ///
+    /// Levels that are not mentioned keep their default color, `none` turns
+    /// the color off, and malformed entries are ignored.
+    fn parse(theme: &str) -> Self {
+        let mut palette = Self::default();
+        for entry in theme.split(',') {
+            let Some((level, color)) = entry.split_once('=') else {
+                continue;
+            };
+            let color = match color.trim() {
+                s if s.eq_ignore_ascii_case("none") => None,
+                s => match s.parse() {
+                    Ok(color) => Some(color),
+                    Err(_) => continue,
+                },
+            };
+            let slot = match level.trim() {
+                "trace" => &mut palette.trace,
+                "debug" => &mut palette.debug,
+                "info" => &mut palette.info,
+                "warn" => &mut palette.warn,
+                "error" => &mut palette.error,
+                _ => continue,
+            };
+            *slot = color;
+        }
+        palette
+    }
+
+    fn color(&self, level: &NotificationLevel) -> Option<Color> {
+        match level {
+            NotificationLevel::Trace => self.trace,
+            NotificationLevel::Debug => self.debug,
+            NotificationLevel::Info => self.info,
+            NotificationLevel::Warn => self.warn,
+            NotificationLevel::Error => self.error,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            trace: NotificationLevel::Trace.fg_color(),
+            debug: NotificationLevel::Debug.fg_color(),
+            info: NotificationLevel::Info.fg_color(),
+            warn: NotificationLevel::Warn.fg_color(),
+            error: NotificationLevel::Error.fg_color(),
+        }
+    }
+}
+
 /// A [`tracing::Subscriber`] [`Layer`][`tracing_subscriber::Layer`] that corresponds to Rustup's
 /// optional `opentelemetry` (a.k.a. `otel`) feature.
 #[cfg(feature = "otel")]
@@ -133,6 +298,25 @@ where
         .with_filter(env_filter)
 }
 
+/// The trace context that the process invoking rustup, e.g. a CI
+/// orchestrator, passed down in the `TRACEPARENT` and `TRACESTATE`
+/// variables, as extracted by the global text map propagator.
+///
+/// Making it the parent of rustup's root span links rustup's spans into the
+/// caller's trace.
+#[cfg(feature = "otel")]
+pub(crate) fn parent_context(process: &Process) -> opentelemetry::Context {
+    use std::collections::HashMap;
+
+    let mut carrier = HashMap::new();
+    for (key, var) in [("traceparent", "TRACEPARENT"), ("tracestate", "TRACESTATE")] {
+        if let Ok(value) = process.var(var) {
+            carrier.insert(key.to_owned(), value);
+        }
+    }
+    opentelemetry::global::get_text_map_propagator(|propagator| propagator.extract(&carrier))
+}
+
 /// The default `opentelemetry` tracer used across Rustup.
 ///
 /// # Note
@@ -163,3 +347,174 @@ fn telemetry_default_tracer() -> Tracer {
         .install_batch(opentelemetry_sdk::runtime::Tokio)
         .expect("error installing `OtlpTracePipeline` in the current `tokio` runtime")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::time::{Duration, SystemTime};
+
+    use termcolor::Color;
+
+    use super::Palette;
+    use crate::currentprocess::TestProcess;
+    use crate::test::Env;
+    use crate::utils::notify::NotificationLevel;
+
+    #[test]
+    fn event_formatter_without_time() {
+        let tp = TestProcess::default();
+        tracing::info!("syncing channel updates");
+
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        assert_eq!(stderr, "info: syncing channel updates\n");
+    }
+
+    #[test]
+    fn event_formatter_with_time() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_LOG_TIME", "1");
+        let tp = TestProcess::with_vars(vars);
+        tracing::info!("syncing channel updates");
+
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        let (time, rest) = stderr.split_once(' ').unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(time).is_ok(), "{time}");
+        assert_eq!(rest, "info: syncing channel updates\n");
+    }
+
+    #[test]
+    fn event_formatter_uses_process_clock() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_LOG_TIME", "1");
+        let tp = TestProcess::with_vars(vars);
+        tp.set_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        tracing::info!("syncing channel updates");
+
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        assert_eq!(
+            stderr,
+            "2023-11-14T22:13:20.000000Z info: syncing channel updates\n"
+        );
+    }
+
+    #[test]
+    fn nested_test_processes() {
+        let outer = TestProcess::default();
+        tracing::info!("outer before");
+        {
+            let inner = TestProcess::default();
+            tracing::info!("inner");
+            assert_eq!(String::from_utf8(inner.stderr()).unwrap(), "info: inner\n");
+        }
+        tracing::info!("outer after");
+
+        let stderr = String::from_utf8(outer.stderr()).unwrap();
+        assert_eq!(stderr, "info: outer before\ninfo: outer after\n");
+    }
+
+    #[cfg(feature = "otel")]
+    #[tokio::test]
+    async fn parent_context_from_traceparent() {
+        use opentelemetry::trace::{TraceContextExt, TraceId};
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let mut vars = HashMap::new();
+        vars.env(
+            "TRACEPARENT",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        );
+        let tp = TestProcess::with_vars(vars);
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+
+        let parent = super::parent_context(&tp.process);
+        assert_eq!(parent.span().span_context().trace_id(), trace_id);
+
+        let span = tracing::info_span!("rustup");
+        span.set_parent(parent);
+        assert_eq!(span.context().span().span_context().trace_id(), trace_id);
+    }
+
+    #[test]
+    fn palette_parse() {
+        let palette = Palette::parse("warn=cyan, error=magenta,info=none");
+        assert_eq!(palette.color(&NotificationLevel::Trace), Some(Color::Blue));
+        assert_eq!(
+            palette.color(&NotificationLevel::Debug),
+            Some(Color::Magenta)
+        );
+        assert_eq!(palette.color(&NotificationLevel::Info), None);
+        assert_eq!(palette.color(&NotificationLevel::Warn), Some(Color::Cyan));
+        assert_eq!(
+            palette.color(&NotificationLevel::Error),
+            Some(Color::Magenta)
+        );
+
+        let palette = Palette::parse("info=green,bogus=red,warn=notacolor,error");
+        assert_eq!(palette.color(&NotificationLevel::Info), Some(Color::Green));
+        assert_eq!(palette.color(&NotificationLevel::Warn), Some(Color::Yellow));
+        assert_eq!(palette.color(&NotificationLevel::Error), Some(Color::Red));
+
+        assert_eq!(Palette::parse(""), Palette::default());
+    }
+
+    #[test]
+    fn event_formatter_with_icons() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_LOG_ICONS", "1");
+        vars.env("LANG", "en_US.UTF-8");
+        let tp = TestProcess::with_vars(vars);
+        tracing::info!("syncing channel updates");
+        tracing::warn!("skipping");
+
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        assert_eq!(stderr, "\u{2713} syncing channel updates\n! skipping\n");
+    }
+
+    #[test]
+    fn event_formatter_reuses_prefix_buf() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_TERM_COLOR", "always");
+        let tp = TestProcess::with_vars(vars);
+        tracing::warn!("skipping");
+        tracing::info!("syncing channel updates");
+
+        // The shorter prefix must not carry over any bytes of the longer one.
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        let lines = stderr.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("warn: \u{1b}[0mskipping"), "{stderr:?}");
+        assert!(
+            lines[1].ends_with("info: \u{1b}[0msyncing channel updates"),
+            "{stderr:?}"
+        );
+        assert!(!lines[1].contains("warn"), "{stderr:?}");
+
+        // The allocation survives between uses on the same thread.
+        let first = super::with_prefix_buf(|buf| {
+            buf.write_all(b"error: ").unwrap();
+            buf.as_slice().as_ptr()
+        });
+        let second = super::with_prefix_buf(|buf| {
+            assert!(buf.is_empty());
+            buf.write_all(b"info: ").unwrap();
+            buf.as_slice().as_ptr()
+        });
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn event_formatter_icons_need_unicode() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_LOG_ICONS", "1");
+        vars.env("LANG", "C");
+        let tp = TestProcess::with_vars(vars);
+        tracing::warn!("skipping");
+
+        let stderr = String::from_utf8(tp.stderr()).unwrap();
+        assert_eq!(stderr, "warn: skipping\n");
+    }
+}
@@ -9,7 +9,10 @@ use crate::{
     toolchain::ResolvableLocalToolchainName,
 };
 
-#[cfg_attr(feature = "otel", tracing::instrument)]
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(skip_all, fields(tool = arg0, arg_count = tracing::field::Empty))
+)]
 pub async fn main(arg0: &str, current_dir: PathBuf, process: &Process) -> Result<ExitStatus> {
     self_update::cleanup_self_updater(process)?;
 
@@ -30,8 +33,77 @@ pub async fn main(arg0: &str, current_dir: PathBuf, process: &Process) -> Result
         .args_os()
         .skip(1 + toolchain.is_some() as usize)
         .collect();
+    #[cfg(feature = "otel")]
+    tracing::Span::current().record("arg_count", cmd_args.len());
 
     let cfg = set_globals(current_dir, false, true, process)?;
     let cmd = cfg.local_toolchain(toolchain).await?.command(arg0)?;
     run_command_for_dir(cmd, arg0, &cmd_args)
 }
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    use super::*;
+    use crate::currentprocess::TestProcess;
+
+    #[derive(Default)]
+    struct Fields(HashMap<String, String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    struct Capture(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    impl<S: Subscriber> Layer<S> for Capture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn span_records_proxied_tool() {
+        let home = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let vars = HashMap::from([
+            (
+                "CARGO_HOME".to_owned(),
+                home.path().join("cargo").display().to_string(),
+            ),
+            (
+                "RUSTUP_HOME".to_owned(),
+                home.path().join("rustup").display().to_string(),
+            ),
+        ]);
+        let tp = TestProcess::new(home.path(), &["cargo", "build", "-v"], vars, "");
+
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let _guard = tracing_subscriber::registry()
+            .with(Capture(spans.clone()))
+            .set_default();
+        // There's no toolchain to proxy to, but the span is entered first.
+        let _ = main("cargo", home.path().to_owned(), &tp.process).await;
+
+        let spans = spans.lock().unwrap();
+        assert!(spans
+            .iter()
+            .any(|s| s.get("tool").map(String::as_str) == Some("cargo")));
+    }
+}
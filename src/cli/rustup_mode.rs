@@ -832,8 +832,15 @@ async fn update(cfg: &mut Cfg<'_>, opts: UpdateOpts) -> Result<utils::ExitCode>
             let profile = cfg.get_profile()?;
             let status = match DistributableToolchain::new(cfg, desc.clone()) {
                 Ok(mut d) => {
-                    d.update_extra(&components, &targets, profile, force, allow_downgrade)
-                        .await?
+                    d.update_extra(
+                        &components,
+                        &targets,
+                        profile,
+                        force,
+                        allow_downgrade,
+                        false,
+                    )
+                    .await?
                 }
                 Err(RustupError::ToolchainNotInstalled(_)) => {
                     DistributableToolchain::install(
@@ -843,6 +850,7 @@ async fn update(cfg: &mut Cfg<'_>, opts: UpdateOpts) -> Result<utils::ExitCode>
                         &targets,
                         profile,
                         force,
+                        false,
                     )
                     .await?
                     .0
@@ -1299,10 +1307,17 @@ async fn override_add(
         Err(e @ RustupError::ToolchainNotInstalled(_)) => match &toolchain_name {
             ToolchainName::Custom(_) => Err(e)?,
             ToolchainName::Official(desc) => {
-                let status =
-                    DistributableToolchain::install(cfg, desc, &[], &[], cfg.get_profile()?, false)
-                        .await?
-                        .0;
+                let status = DistributableToolchain::install(
+                    cfg,
+                    desc,
+                    &[],
+                    &[],
+                    cfg.get_profile()?,
+                    false,
+                    false,
+                )
+                .await?
+                .0;
                 writeln!(cfg.process.stdout().lock())?;
                 common::show_channel_update(
                     cfg,
@@ -32,12 +32,12 @@
 
 use std::borrow::Cow;
 use std::env::consts::EXE_SUFFIX;
+use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
 use std::process::Command;
 use std::str::FromStr;
-use std::{env, fmt};
 
 use anyhow::{anyhow, Context, Result};
 use cfg_if::cfg_if;
@@ -99,7 +99,9 @@ pub(crate) struct InstallOpts<'a> {
 }
 
 impl<'a> InstallOpts<'a> {
-    fn install(self, cfg: &mut Cfg<'_>) -> Result<Option<ToolchainDesc>> {
+    /// Applies the options to `cfg` and returns the toolchain to install, if
+    /// any. A dry run keeps the profile and host triple out of the settings file.
+    fn install(self, cfg: &mut Cfg<'_>, dry_run: bool) -> Result<Option<ToolchainDesc>> {
         let Self {
             default_host_triple,
             default_toolchain,
@@ -110,15 +112,24 @@ impl<'a> InstallOpts<'a> {
             targets,
         } = self;
 
-        cfg.set_profile(profile)?;
+        if dry_run {
+            cfg.set_profile_override(profile);
+        } else {
+            cfg.set_profile(profile)?;
+        }
 
-        if let Some(default_host_triple) = &default_host_triple {
+        let host_triple = if let Some(default_host_triple) = &default_host_triple {
             // Set host triple now as it will affect resolution of toolchain_str
             info!("setting default host triple to {}", default_host_triple);
-            cfg.set_default_host_triple(default_host_triple.to_owned())?;
+            if !dry_run {
+                cfg.set_default_host_triple(default_host_triple.to_owned())?;
+            }
+            TargetTriple::new(default_host_triple)
         } else {
-            info!("default host triple is {}", cfg.get_default_host_triple()?);
-        }
+            let host_triple = cfg.get_default_host_triple()?;
+            info!("default host triple is {}", host_triple);
+            host_triple
+        };
 
         let user_specified_something = default_toolchain.is_some()
             || !targets.is_empty()
@@ -157,7 +168,7 @@ impl<'a> InstallOpts<'a> {
                         MaybeOfficialToolchainName::None => unreachable!(),
                         MaybeOfficialToolchainName::Some(n) => n,
                     };
-                    Some(toolchain_name.resolve(&cfg.get_default_host_triple()?)?)
+                    Some(toolchain_name.resolve(&host_triple)?)
                 }
                 None => match cfg.get_default()? {
                     // Default is installable
@@ -167,7 +178,7 @@ impl<'a> InstallOpts<'a> {
                     None => Some(
                         "stable"
                             .parse::<PartialToolchainDesc>()?
-                            .resolve(&cfg.get_default_host_triple()?)?,
+                            .resolve(&host_triple)?,
                     ),
                 },
             })
@@ -493,6 +504,7 @@ pub(crate) async fn install(
     no_prompt: bool,
     verbose: bool,
     quiet: bool,
+    dry_run: bool,
     mut opts: InstallOpts<'_>,
     process: &Process,
 ) -> Result<utils::ExitCode> {
@@ -524,6 +536,17 @@ pub(crate) async fn install(
 
     let mut term = process.stdout().terminal(process);
 
+    if dry_run {
+        md(&mut term, pre_install_msg(opts.no_modify_path, process)?);
+        md(&mut term, current_install_opts(&opts, process));
+        if let Err(e) = maybe_install_rust(current_dir, verbose, quiet, true, opts, process).await {
+            report_error(&e, process);
+            return Ok(utils::ExitCode(1));
+        }
+        info!("dry run: no changes have been made");
+        return Ok(utils::ExitCode(0));
+    }
+
     #[cfg(windows)]
     windows::maybe_install_msvc(&mut term, no_prompt, quiet, &opts, process).await?;
 
@@ -551,7 +574,7 @@ pub(crate) async fn install(
     }
 
     let no_modify_path = opts.no_modify_path;
-    if let Err(e) = maybe_install_rust(current_dir, verbose, quiet, opts, process).await {
+    if let Err(e) = maybe_install_rust(current_dir, verbose, quiet, false, opts, process).await {
         report_error(&e, process);
 
         // On windows, where installation happens in a console
@@ -609,16 +632,12 @@ fn rustc_or_cargo_exists_in_path(process: &Process) -> Result<()> {
             .any(|c| c == Component::Normal(".cargo".as_ref()))
     }
 
-    if let Some(paths) = process.var_os("PATH") {
-        let paths = env::split_paths(&paths).filter(ignore_paths);
-
-        for path in paths {
-            let rustc = path.join(format!("rustc{EXE_SUFFIX}"));
-            let cargo = path.join(format!("cargo{EXE_SUFFIX}"));
+    for path in process.path_entries().into_iter().filter(ignore_paths) {
+        let rustc = path.join(format!("rustc{EXE_SUFFIX}"));
+        let cargo = path.join(format!("cargo{EXE_SUFFIX}"));
 
-            if rustc.exists() || cargo.exists() {
-                return Err(anyhow!("{}", path.to_str().unwrap().to_owned()));
-            }
+        if rustc.exists() || cargo.exists() {
+            return Err(anyhow!("{}", path.to_str().unwrap().to_owned()));
         }
     }
     Ok(())
@@ -685,7 +704,7 @@ fn do_pre_install_sanity_checks(no_prompt: bool, process: &Process) -> Result<()
 fn pre_install_msg(no_modify_path: bool, process: &Process) -> Result<String> {
     let cargo_home = process.cargo_home()?;
     let cargo_home_bin = cargo_home.join("bin");
-    let rustup_home = home::rustup_home()?;
+    let rustup_home = process.rustup_home()?;
 
     if !no_modify_path {
         // Brittle code warning: some duplication in unix::do_add_to_path
@@ -844,32 +863,31 @@ async fn maybe_install_rust(
     current_dir: PathBuf,
     verbose: bool,
     quiet: bool,
+    dry_run: bool,
     opts: InstallOpts<'_>,
     process: &Process,
 ) -> Result<()> {
-    install_bins(process)?;
+    if !dry_run {
+        install_bins(process)?;
 
-    #[cfg(unix)]
-    unix::do_write_env_files(process)?;
+        #[cfg(unix)]
+        unix::do_write_env_files(process)?;
 
-    if !opts.no_modify_path {
-        do_add_to_path(process)?;
+        if !opts.no_modify_path {
+            do_add_to_path(process)?;
+        }
     }
 
     // If RUSTUP_HOME is not set, make sure it exists
     if process.var_os("RUSTUP_HOME").is_none() {
-        let home = process
-            .home_dir()
-            .map(|p| p.join(".rustup"))
-            .ok_or_else(|| anyhow::anyhow!("could not find home dir to put .rustup in"))?;
-
+        let home = process.rustup_home()?;
         fs::create_dir_all(home).context("unable to create ~/.rustup")?;
     }
 
     let mut cfg = common::set_globals(current_dir, verbose, quiet, process)?;
 
     let (components, targets) = (opts.components, opts.targets);
-    let toolchain = opts.install(&mut cfg)?;
+    let toolchain = opts.install(&mut cfg, dry_run)?;
     if let Some(ref desc) = toolchain {
         let status = if Toolchain::exists(&cfg, &desc.into())? {
             warn!("Updating existing toolchain, profile choice will be ignored");
@@ -880,7 +898,14 @@ async fn maybe_install_rust(
             // For now, we error.
             let mut toolchain = DistributableToolchain::new(&cfg, desc.clone())?;
             toolchain
-                .update(components, targets, cfg.get_profile()?)
+                .update_extra(
+                    components,
+                    targets,
+                    cfg.get_profile()?,
+                    true,
+                    false,
+                    dry_run,
+                )
                 .await?
         } else {
             DistributableToolchain::install(
@@ -890,11 +915,16 @@ async fn maybe_install_rust(
                 targets,
                 cfg.get_profile()?,
                 true,
+                dry_run,
             )
             .await?
             .0
         };
 
+        if dry_run {
+            return Ok(());
+        }
+
         cfg.set_default(Some(&desc.into()))?;
         writeln!(process.stdout().lock())?;
         common::show_channel_update(&cfg, PackageUpdate::Toolchain(desc.clone()), Ok(status))?;
@@ -931,7 +961,7 @@ pub(crate) fn uninstall(no_prompt: bool, process: &Process) -> Result<utils::Exi
     info!("removing rustup home");
 
     // Delete RUSTUP_HOME
-    let rustup_dir = home::rustup_home()?;
+    let rustup_dir = process.rustup_home()?;
     if rustup_dir.exists() {
         utils::remove_dir("rustup_home", &rustup_dir, &|_: Notification<'_>| {})?;
     }
@@ -1285,7 +1315,7 @@ mod tests {
                     .unwrap()
                     .resolve(&cfg.get_default_host_triple().unwrap())
                     .unwrap(),
-                opts.install(&mut cfg)
+                opts.install(&mut cfg, false)
                     .unwrap() // result
                     .unwrap() // option
             );
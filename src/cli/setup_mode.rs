@@ -63,6 +63,10 @@ struct RustupInit {
     #[arg(long)]
     no_modify_path: bool,
 
+    /// Print what would be installed without making any changes
+    #[arg(long)]
+    dry_run: bool,
+
     /// Secret command used during self-update. Not for users
     #[arg(long, hide = true)]
     self_replace: bool,
@@ -87,6 +91,7 @@ pub async fn main(current_dir: PathBuf, process: &Process) -> Result<utils::Exit
         target,
         no_update_default_toolchain,
         no_modify_path,
+        dry_run,
         self_replace,
         dump_testament,
     } = match RustupInit::try_parse() {
@@ -121,5 +126,14 @@ pub async fn main(current_dir: PathBuf, process: &Process) -> Result<utils::Exit
         targets: &target.iter().map(|s| &**s).collect::<Vec<_>>(),
     };
 
-    self_update::install(current_dir, no_prompt, verbose, quiet, opts, process).await
+    self_update::install(
+        current_dir,
+        no_prompt,
+        verbose,
+        quiet,
+        dry_run,
+        opts,
+        process,
+    )
+    .await
 }
@@ -321,7 +321,8 @@ impl<'a> Cfg<'a> {
             rustup_dir.join("tmp"),
             dist_root_server.as_str(),
             Box::new(move |n| (notify_clone)(n.into())),
-        );
+        )
+        .with_rng_seed(process.rng_seed());
         let dist_root = dist_root_server + "/dist";
 
         let cfg = Self {
@@ -817,6 +818,7 @@ impl<'a> Cfg<'a> {
                     &targets,
                     profile.unwrap_or(Profile::Default),
                     false,
+                    false,
                 )
                 .await?
                 .1
@@ -919,7 +921,7 @@ impl<'a> Cfg<'a> {
         // Update toolchains and collect the results
         let channels = tokio_stream::iter(channels).then(|(desc, mut distributable)| async move {
             let st = distributable
-                .update_extra(&[], &[], profile, force_update, false)
+                .update_extra(&[], &[], profile, force_update, false, false)
                 .await;
             if let Err(ref e) = st {
                 (self.notify_handler)(Notification::NonFatalError(e));
@@ -1,23 +1,34 @@
 use std::env;
 use std::ffi::OsString;
 use std::fmt::Debug;
+use std::fs;
 use std::io;
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 #[cfg(feature = "test")]
 use std::{
     collections::HashMap,
     io::Cursor,
-    path::Path,
-    sync::{Arc, Mutex},
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
 };
 
 use anyhow::{Context, Result};
+use clap_complete::Shell;
+use once_cell::sync::OnceCell;
 #[cfg(feature = "test")]
 use tracing::subscriber::DefaultGuard;
+use tracing::warn;
 #[cfg(feature = "test")]
 use tracing_subscriber::util::SubscriberInitExt;
 
+#[cfg(feature = "test")]
+use crate::dist::component::PermissionPolicy;
+#[cfg(feature = "test")]
+use crate::utils::notify::NotificationLevel;
+
 pub mod filesource;
 pub mod terminalsource;
 
@@ -55,7 +66,9 @@ impl Process {
         home::env::cargo_home_with_env(self).context("failed to determine cargo home")
     }
 
-    pub(crate) fn rustup_home(&self) -> Result<PathBuf> {
+    /// Resolves the rustup home: `RUSTUP_HOME` if set, relative to the
+    /// current directory, or else `.rustup` in the home directory.
+    pub fn rustup_home(&self) -> Result<PathBuf> {
         home::env::rustup_home_with_env(self).context("failed to determine rustup home dir")
     }
 
@@ -70,6 +83,27 @@ impl Process {
         }
     }
 
+    /// Reads `key` as a flag: `1`, `true`, `yes` and `on`, in any case, are
+    /// true; any other value, as well as an unset or non-Unicode variable, is
+    /// false.
+    pub fn var_bool(&self, key: &str) -> bool {
+        self.var(key).is_ok_and(|v| {
+            ["1", "true", "yes", "on"]
+                .iter()
+                .any(|t| v.trim().eq_ignore_ascii_case(t))
+        })
+    }
+
+    /// Splits `PATH` with the platform's separator, skipping empty entries.
+    pub fn path_entries(&self) -> Vec<PathBuf> {
+        match self.var_os("PATH") {
+            Some(path) => env::split_paths(&path)
+                .filter(|p| !p.as_os_str().is_empty())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
     pub(crate) fn var_os(&self, key: &str) -> Option<OsString> {
         match self {
             Process::OSProcess(_) => env::var_os(key),
@@ -78,6 +112,18 @@ impl Process {
         }
     }
 
+    /// Like [`Process::var_os`], but converted to a `String` for call sites
+    /// that can live with an approximation of odd values, e.g. paths shown
+    /// to the user.
+    ///
+    /// The conversion is lossy: invalid UTF-8 on Unix and unpaired
+    /// surrogates on Windows are replaced with `U+FFFD`, so the result may
+    /// not name the same file as the original value.
+    pub fn var_os_lossy(&self, key: &str) -> Option<String> {
+        self.var_os(key)
+            .map(|value| value.to_string_lossy().into_owned())
+    }
+
     pub(crate) fn args(&self) -> Box<dyn Iterator<Item = String> + '_> {
         match self {
             Process::OSProcess(_) => Box::new(env::args()),
@@ -98,7 +144,10 @@ impl Process {
         match self {
             Process::OSProcess(_) => Box::new(io::stdin()),
             #[cfg(feature = "test")]
-            Process::TestProcess(p) => Box::new(filesource::TestStdin(p.stdin.clone())),
+            Process::TestProcess(p) => {
+                let stdin = filesource::TestStdin::new(p.stdin.clone()).timing_out(p.stdin_timeout);
+                Box::new(stdin)
+            }
         }
     }
 
@@ -106,15 +155,66 @@ impl Process {
         match self {
             Process::OSProcess(_) => Box::new(io::stdout()),
             #[cfg(feature = "test")]
-            Process::TestProcess(p) => Box::new(filesource::TestWriter(p.stdout.clone())),
+            Process::TestProcess(p) => {
+                let writer = filesource::TestWriter::new(p.stdout.clone())
+                    .with_cap(p.output_cap)
+                    .line_buffered(p.line_buffered);
+                Box::new(writer)
+            }
         }
     }
 
     pub(crate) fn stderr(&self) -> Box<dyn filesource::Writer> {
         match self {
-            Process::OSProcess(_) => Box::new(io::stderr()),
+            Process::OSProcess(p) => match p.log_file.get() {
+                Some(log_file) => Box::new(filesource::TeeWriter::new(
+                    Box::new(io::stderr()),
+                    log_file.clone(),
+                )),
+                None => Box::new(io::stderr()),
+            },
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => {
+                let writer = filesource::TestWriter::new(p.stderr.clone())
+                    .with_cap(p.output_cap)
+                    .line_buffered(p.line_buffered);
+                Box::new(writer)
+            }
+        }
+    }
+
+    /// Mirrors everything written to stderr from now on into `file`, with
+    /// the terminal's color codes stripped.
+    ///
+    /// Test processes keep their stderr in memory and ignore this.
+    pub(crate) fn set_log_file(&self, file: fs::File) {
+        match self {
+            Process::OSProcess(p) => {
+                let file = Arc::new(Mutex::new(filesource::StripAnsi::new(file)));
+                // Only the first log file of a process is used.
+                let _ = p.log_file.set(file);
+            }
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => {}
+        }
+    }
+
+    /// Returns the OS-assigned identifier of this process, or the identifier
+    /// stored for a test process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustup::currentprocess::Process;
+    ///
+    /// let process = Process::os();
+    /// assert_eq!(process.id(), std::process::id());
+    /// ```
+    pub fn id(&self) -> u32 {
+        match self {
+            Process::OSProcess(_) => std::process::id(),
             #[cfg(feature = "test")]
-            Process::TestProcess(p) => Box::new(filesource::TestWriter(p.stderr.clone())),
+            Process::TestProcess(p) => p.id,
         }
     }
 
@@ -125,6 +225,286 @@ impl Process {
             Process::TestProcess(p) => Ok(p.cwd.clone()),
         }
     }
+
+    /// Returns the current time: the system clock's, unless a test process
+    /// has been given a time with [`TestProcess::set_time`].
+    pub fn now(&self) -> SystemTime {
+        match self {
+            Process::OSProcess(_) => SystemTime::now(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => p
+                .clock
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrap_or_else(SystemTime::now),
+        }
+    }
+
+    /// Returns whether this process runs with elevated privileges: as root or
+    /// through `sudo` on Unix, or with an elevated token on Windows.
+    ///
+    /// A test process is elevated only if it has `RUSTUP_TEST_ELEVATED` set
+    /// to a true value.
+    pub fn is_elevated(&self) -> bool {
+        match self {
+            Process::OSProcess(_) => os_is_elevated(self),
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => self.var_bool("RUSTUP_TEST_ELEVATED"),
+        }
+    }
+
+    /// Returns whether `stdout` and `stderr` write to the same file, pipe or
+    /// terminal, in which case their output interleaves and carriage-return
+    /// progress lines can end up in the middle of other output.
+    ///
+    /// A test process reports this only if it has `RUSTUP_TEST_SAME_OUTPUT`
+    /// set to a true value.
+    pub fn stdout_stderr_same_target(&self) -> bool {
+        match self {
+            Process::OSProcess(_) => os_stdout_stderr_same_target(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => self.var_bool("RUSTUP_TEST_SAME_OUTPUT"),
+        }
+    }
+
+    /// Returns a seed for the random number generators that name temporary
+    /// files and directories.
+    ///
+    /// An OS process draws it from the OS's entropy source. A test process
+    /// uses `RUSTUP_TEST_RNG_SEED` if set, so that the names are reproducible,
+    /// and its id otherwise.
+    pub fn rng_seed(&self) -> u64 {
+        match self {
+            Process::OSProcess(_) => rand::random(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => self
+                .var("RUSTUP_TEST_RNG_SEED")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(u64::from(p.id)),
+        }
+    }
+
+    /// The encoding output is expected to be rendered in, so that Unicode
+    /// symbols can be replaced with ASCII where they would come out garbled.
+    ///
+    /// On Windows, this is the code page of the console. Elsewhere, and for
+    /// test processes, it follows the first non-empty one of `LC_ALL`,
+    /// `LC_CTYPE` and `LANG`.
+    pub fn output_encoding(&self) -> Encoding {
+        match self {
+            Process::OSProcess(_) => os_output_encoding(self),
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => locale_encoding(self),
+        }
+    }
+
+    /// The number of columns output should be wrapped to.
+    ///
+    /// `RUSTUP_TERM_WIDTH` takes precedence, and is ignored with a warning if
+    /// it isn't a positive number. Otherwise an OS process asks the terminal
+    /// `stderr` is attached to. Failing that, the width is 80 columns.
+    pub fn terminal_width(&self) -> usize {
+        if let Ok(s) = self.var("RUSTUP_TERM_WIDTH") {
+            match s.parse::<usize>() {
+                Ok(width) if width > 0 => return width,
+                _ => warn!("ignoring invalid RUSTUP_TERM_WIDTH value '{s}'"),
+            }
+        }
+        let detected = match self {
+            Process::OSProcess(_) => os_terminal_width(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => None,
+        };
+        detected.unwrap_or(80)
+    }
+
+    /// Guesses the shell rustup was run from, e.g. to pick a completion
+    /// script: `SHELL` names it on Unix and in MSYS-like environments,
+    /// otherwise `PSModulePath` points to PowerShell.
+    ///
+    /// Windows sets `PSModulePath` for every process, so this reports
+    /// PowerShell for `cmd.exe` as well.
+    pub fn detect_shell(&self) -> Option<Shell> {
+        if let Some(shell) = self.var_os("SHELL") {
+            let name = Path::new(&shell)
+                .file_stem()?
+                .to_str()?
+                .to_ascii_lowercase();
+            return match &*name {
+                "bash" => Some(Shell::Bash),
+                "elvish" => Some(Shell::Elvish),
+                "fish" => Some(Shell::Fish),
+                "pwsh" | "powershell" => Some(Shell::PowerShell),
+                "zsh" => Some(Shell::Zsh),
+                _ => None,
+            };
+        }
+        self.var_os("PSModulePath").map(|_| Shell::PowerShell)
+    }
+}
+
+/// The result of [`Process::output_encoding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// Any other encoding, in which only ASCII can be relied upon.
+    Other,
+}
+
+#[cfg(any(unix, feature = "test"))]
+fn locale_encoding(process: &Process) -> Encoding {
+    let utf8 = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|name| process.var(name).ok().filter(|s| !s.is_empty()))
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        });
+    if utf8 {
+        Encoding::Utf8
+    } else {
+        Encoding::Other
+    }
+}
+
+#[cfg(unix)]
+fn os_output_encoding(process: &Process) -> Encoding {
+    locale_encoding(process)
+}
+
+#[cfg(windows)]
+fn os_output_encoding(_: &Process) -> Encoding {
+    use windows_sys::Win32::System::Console::GetConsoleOutputCP;
+
+    // `CP_UTF8`. Without a console, i.e. with a code page of 0, the output
+    // goes to a program that is assumed to cope with UTF-8.
+    match unsafe { GetConsoleOutputCP() } {
+        0 | 65001 => Encoding::Utf8,
+        _ => Encoding::Other,
+    }
+}
+
+#[cfg(unix)]
+fn os_terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => Some(size.ws_col.into()),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn os_terminal_width() -> Option<usize> {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleScreenBufferInfo, GetStdHandle, CONSOLE_SCREEN_BUFFER_INFO, STD_ERROR_HANDLE,
+    };
+
+    let window = unsafe {
+        let mut info: CONSOLE_SCREEN_BUFFER_INFO = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_ERROR_HANDLE), &mut info) == 0 {
+            return None;
+        }
+        info.srWindow
+    };
+    usize::try_from(window.Right - window.Left + 1)
+        .ok()
+        .filter(|&width| width > 0)
+}
+
+#[cfg(unix)]
+fn os_is_elevated(process: &Process) -> bool {
+    process.var_os("SUDO_USER").is_some() || unsafe { libc::geteuid() } == 0
+}
+
+#[cfg(windows)]
+fn os_is_elevated(_: &Process) -> bool {
+    use std::mem;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    unsafe {
+        let mut token: HANDLE = 0;
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut len = 0;
+        let ok = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut TOKEN_ELEVATION as *mut _,
+            mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut len,
+        );
+        CloseHandle(token);
+        ok != 0 && elevation.TokenIsElevated != 0
+    }
+}
+
+#[cfg(unix)]
+fn os_stdout_stderr_same_target() -> bool {
+    use std::os::fd::AsFd;
+
+    same_target(io::stdout().as_fd(), io::stderr().as_fd())
+}
+
+/// Whether `a` and `b` refer to the same device and inode.
+#[cfg(unix)]
+fn same_target(a: std::os::fd::BorrowedFd<'_>, b: std::os::fd::BorrowedFd<'_>) -> bool {
+    use std::os::fd::{AsRawFd, BorrowedFd};
+
+    let stat = |fd: BorrowedFd<'_>| {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        (unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } == 0).then_some(stat)
+    };
+    match (stat(a), stat(b)) {
+        (Some(a), Some(b)) => a.st_dev == b.st_dev && a.st_ino == b.st_ino,
+        _ => false,
+    }
+}
+
+#[cfg(windows)]
+fn os_stdout_stderr_same_target() -> bool {
+    use std::os::windows::io::AsHandle;
+
+    same_target(io::stdout().as_handle(), io::stderr().as_handle())
+}
+
+/// Whether `a` and `b` are the same handle, or handles to the same file on
+/// the same volume.
+#[cfg(windows)]
+fn same_target(
+    a: std::os::windows::io::BorrowedHandle<'_>,
+    b: std::os::windows::io::BorrowedHandle<'_>,
+) -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    let (a, b) = (a.as_raw_handle() as HANDLE, b.as_raw_handle() as HANDLE);
+    if a == b {
+        return true;
+    }
+    let info = |handle: HANDLE| unsafe {
+        let mut info: BY_HANDLE_FILE_INFORMATION = std::mem::zeroed();
+        (GetFileInformationByHandle(handle, &mut info) != 0).then_some(info)
+    };
+    match (info(a), info(b)) {
+        (Some(a), Some(b)) => {
+            a.dwVolumeSerialNumber == b.dwVolumeSerialNumber
+                && a.nFileIndexHigh == b.nFileIndexHigh
+                && a.nFileIndexLow == b.nFileIndexLow
+        }
+        _ => false,
+    }
 }
 
 impl home::env::Env for Process {
@@ -132,7 +512,13 @@ impl home::env::Env for Process {
         match self {
             Process::OSProcess(_) => self.var("HOME").ok().map(|v| v.into()),
             #[cfg(feature = "test")]
-            Process::TestProcess(_) => home::env::OS_ENV.home_dir(),
+            Process::TestProcess(p) => {
+                let key = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+                match p.vars.get(key) {
+                    Some(home) => Some(home.into()),
+                    None => home::env::OS_ENV.home_dir(),
+                }
+            }
         }
     }
 
@@ -159,6 +545,9 @@ impl home::env::Env for Process {
 pub struct OSProcess {
     pub(self) stderr_is_a_tty: bool,
     pub(self) stdout_is_a_tty: bool,
+    /// The file everything written to stderr is also appended to, once
+    /// [`Process::set_log_file`] is called. Shared between clones.
+    log_file: Arc<OnceCell<Arc<Mutex<filesource::StripAnsi<fs::File>>>>>,
 }
 
 impl OSProcess {
@@ -166,8 +555,21 @@ impl OSProcess {
         OSProcess {
             stderr_is_a_tty: io::stderr().is_terminal(),
             stdout_is_a_tty: io::stdout().is_terminal(),
+            log_file: Arc::default(),
         }
     }
+
+    /// Constructs an OS process that reports the given TTY status for its
+    /// stdout and stderr instead of querying the real terminals.
+    ///
+    /// This allows exercising TTY-dependent code paths without a terminal.
+    pub fn with_ttys(stdout: bool, stderr: bool) -> Process {
+        Process::OSProcess(OSProcess {
+            stderr_is_a_tty: stderr,
+            stdout_is_a_tty: stdout,
+            log_file: Arc::default(),
+        })
+    }
 }
 
 impl Default for OSProcess {
@@ -196,9 +598,10 @@ impl TestProcess {
             cwd: cwd.as_ref().to_path_buf(),
             args: args.iter().map(|s| s.as_ref().to_string()).collect(),
             vars,
-            stdin: Arc::new(Mutex::new(Cursor::new(stdin.to_string()))),
+            stdin: Arc::new(Mutex::new(Cursor::new(stdin.as_bytes().to_vec()))),
             stdout: Arc::default(),
             stderr: Arc::default(),
+            ..Default::default()
         })
     }
 
@@ -209,6 +612,94 @@ impl TestProcess {
         })
     }
 
+    /// Creates a test process with a snapshot of the real environment
+    /// variables, working directory and arguments, with `overrides` applied
+    /// on top of the variables.
+    ///
+    /// Variables whose name or value is not valid Unicode are left out.
+    pub fn from_real_env(overrides: HashMap<String, String>) -> Self {
+        let mut vars = env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect::<HashMap<_, _>>();
+        vars.extend(overrides);
+        Self::from(TestContext {
+            cwd: env::current_dir().unwrap_or_default(),
+            args: env::args_os()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect(),
+            vars,
+            ..Default::default()
+        })
+    }
+
+    /// Creates a test process that only retains the last `cap` bytes written
+    /// to each of its stdout and stderr.
+    pub fn with_output_cap(cap: usize) -> Self {
+        Self::from(TestContext {
+            output_cap: Some(cap),
+            ..Default::default()
+        })
+    }
+
+    /// Creates a test process whose stdout and stderr handles hold back
+    /// partial lines until a newline, until they are flushed with
+    /// [`Writer::flush_all`](filesource::Writer::flush_all) or dropped.
+    pub fn with_line_buffering() -> Self {
+        Self::from(TestContext {
+            line_buffered: true,
+            ..Default::default()
+        })
+    }
+
+    /// Creates a test process where nobody answers prompts: reading a line
+    /// from stdin with a timeout times out right away.
+    pub fn with_stdin_timing_out() -> Self {
+        Self::from(TestContext {
+            stdin_timeout: true,
+            ..Default::default()
+        })
+    }
+
+    /// Freezes the clock returned by [`Process::now`] at `time`.
+    pub fn set_time(&self, time: SystemTime) {
+        *self.clock().lock().unwrap_or_else(|e| e.into_inner()) = Some(time);
+    }
+
+    /// Moves the clock forward by `duration`, freezing it first if needed.
+    pub fn advance_time(&self, duration: Duration) {
+        let mut clock = self.clock().lock().unwrap_or_else(|e| e.into_inner());
+        *clock = Some(clock.unwrap_or_else(SystemTime::now) + duration);
+    }
+
+    fn clock(&self) -> &Arc<Mutex<Option<SystemTime>>> {
+        match &self.process {
+            Process::TestProcess(tp) => &tp.clock,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the level and message of every event logged so far by the
+    /// console logger of this process.
+    pub fn captured_logs(&self) -> Vec<(NotificationLevel, String)> {
+        match &self.process {
+            Process::TestProcess(tp) => tp.logs.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the paths that transactions of this process would have given
+    /// explicit permissions, along with the policy for each.
+    pub fn recorded_permissions(&self) -> Vec<(PathBuf, PermissionPolicy)> {
+        match &self.process {
+            Process::TestProcess(tp) => tp
+                .permissions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            _ => unreachable!(),
+        }
+    }
+
     /// Extracts the stdout from the process
     pub fn stdout(&self) -> Vec<u8> {
         let tp = match &self.process {
@@ -228,6 +719,12 @@ impl TestProcess {
 
         tp.stderr.lock().unwrap_or_else(|e| e.into_inner()).clone()
     }
+
+    /// Extracts the stderr from the process as text, with the ANSI escape
+    /// sequences stripped.
+    pub fn get_stderr_plain(&self) -> String {
+        crate::test::strip_ansi(&self.stderr())
+    }
 }
 
 #[cfg(feature = "test")]
@@ -250,7 +747,7 @@ impl Default for TestProcess {
 }
 
 #[cfg(feature = "test")]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct TestContext {
     pub cwd: PathBuf,
     args: Vec<String>,
@@ -258,4 +755,434 @@ pub struct TestContext {
     stdin: filesource::TestStdinInner,
     stdout: filesource::TestWriterInner,
     stderr: filesource::TestWriterInner,
+    id: u32,
+    /// A frozen time for [`Process::now`], shared between clones.
+    clock: Arc<Mutex<Option<SystemTime>>>,
+    /// How many bytes of stdout and stderr to retain, if not all of them.
+    output_cap: Option<usize>,
+    /// Whether stdout and stderr hold back partial lines.
+    line_buffered: bool,
+    /// Whether reading a line from stdin with a timeout times out right away.
+    stdin_timeout: bool,
+    /// The permission policies that transactions would have applied, by
+    /// path, shared between clones.
+    pub(crate) permissions: Arc<Mutex<Vec<(PathBuf, PermissionPolicy)>>>,
+    /// The events logged while this process was the default subscriber.
+    pub(crate) logs: CapturedLogs,
+}
+
+/// The in-memory log of a [`TestContext`], shared between clones.
+#[cfg(feature = "test")]
+pub(crate) type CapturedLogs = Arc<Mutex<Vec<(NotificationLevel, String)>>>;
+
+#[cfg(feature = "test")]
+impl Default for TestContext {
+    fn default() -> Self {
+        // Hand out distinct fake pids so that test processes sharing one real
+        // process can still be told apart.
+        static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+        Self {
+            cwd: PathBuf::default(),
+            args: Vec::default(),
+            vars: HashMap::default(),
+            stdin: Arc::default(),
+            stdout: Arc::default(),
+            stderr: Arc::default(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            clock: Arc::default(),
+            output_cap: None,
+            line_buffered: false,
+            stdin_timeout: false,
+            permissions: Arc::default(),
+            logs: Arc::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_process_id() {
+        assert_eq!(Process::os().id(), std::process::id());
+    }
+
+    #[test]
+    fn os_process_with_ttys() {
+        let process = OSProcess::with_ttys(false, true);
+        assert!(process.stderr().is_a_tty(&process));
+        assert!(!process.stdout().is_a_tty(&process));
+
+        let process = OSProcess::with_ttys(true, false);
+        assert!(!process.stderr().is_a_tty(&process));
+        assert!(process.stdout().is_a_tty(&process));
+    }
+
+    #[test]
+    fn test_process_home_dir() {
+        let key = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        let home = PathBuf::from("/fake/home");
+        let tp = TestProcess::with_vars(HashMap::from([(
+            key.to_owned(),
+            home.to_string_lossy().into_owned(),
+        )]));
+        assert_eq!(tp.process.home_dir(), Some(home));
+
+        let tp = TestProcess::default();
+        assert_eq!(tp.process.home_dir(), home::env::OS_ENV.home_dir());
+    }
+
+    #[test]
+    fn test_process_rustup_home() {
+        let home_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        let home = PathBuf::from("/fake/home");
+        let tp = TestProcess::with_vars(HashMap::from([(
+            home_var.to_owned(),
+            home.to_string_lossy().into_owned(),
+        )]));
+        assert_eq!(tp.process.rustup_home().unwrap(), home.join(".rustup"));
+
+        let rustup_home = env::temp_dir().join("rustup-home");
+        let tp = TestProcess::with_vars(HashMap::from([
+            (home_var.to_owned(), home.to_string_lossy().into_owned()),
+            (
+                "RUSTUP_HOME".to_owned(),
+                rustup_home.to_string_lossy().into_owned(),
+            ),
+        ]));
+        assert_eq!(tp.process.rustup_home().unwrap(), rustup_home);
+    }
+
+    #[test]
+    fn test_process_from_real_env() {
+        env::set_var("RUSTUP_TEST_REAL_ENV", "real");
+        env::set_var("RUSTUP_TEST_REAL_ENV_OVERRIDDEN", "real");
+        let tp = TestProcess::from_real_env(HashMap::from([(
+            "RUSTUP_TEST_REAL_ENV_OVERRIDDEN".to_owned(),
+            "overridden".to_owned(),
+        )]));
+
+        assert_eq!(tp.process.var("RUSTUP_TEST_REAL_ENV").unwrap(), "real");
+        assert_eq!(
+            tp.process.var("RUSTUP_TEST_REAL_ENV_OVERRIDDEN").unwrap(),
+            "overridden"
+        );
+        assert_eq!(
+            tp.process.current_dir().unwrap(),
+            env::current_dir().unwrap()
+        );
+        assert_eq!(
+            tp.process.args().collect::<Vec<_>>(),
+            env::args().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn var_os_lossy() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TEST_LOSSY".to_owned(),
+            "/opt/rust".to_owned(),
+        )]));
+        assert_eq!(
+            tp.process.var_os_lossy("RUSTUP_TEST_LOSSY").as_deref(),
+            Some("/opt/rust")
+        );
+        assert_eq!(tp.process.var_os_lossy("RUSTUP_TEST_UNSET"), None);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn var_os_lossy_non_unicode() {
+        #[cfg(unix)]
+        let value = {
+            use std::os::unix::ffi::OsStringExt;
+            OsString::from_vec(b"/opt/\xffrust".to_vec())
+        };
+        #[cfg(windows)]
+        let value = {
+            use std::os::windows::ffi::OsStringExt;
+            let mut wide = "/opt/".encode_utf16().collect::<Vec<_>>();
+            wide.push(0xD800);
+            wide.extend("rust".encode_utf16());
+            OsString::from_wide(&wide)
+        };
+        env::set_var("RUSTUP_TEST_LOSSY_NON_UNICODE", value);
+
+        let process = Process::os();
+        assert!(matches!(
+            process.var("RUSTUP_TEST_LOSSY_NON_UNICODE"),
+            Err(env::VarError::NotUnicode(_))
+        ));
+        assert_eq!(
+            process
+                .var_os_lossy("RUSTUP_TEST_LOSSY_NON_UNICODE")
+                .as_deref(),
+            Some("/opt/\u{fffd}rust")
+        );
+    }
+
+    #[test]
+    fn test_process_output_encoding() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "LANG".to_owned(),
+            "en_US.UTF-8".to_owned(),
+        )]));
+        assert_eq!(tp.process.output_encoding(), Encoding::Utf8);
+
+        let tp = TestProcess::with_vars(HashMap::from([
+            ("LC_ALL".to_owned(), "C".to_owned()),
+            ("LANG".to_owned(), "en_US.utf8".to_owned()),
+        ]));
+        assert_eq!(tp.process.output_encoding(), Encoding::Other);
+
+        let tp = TestProcess::with_vars(HashMap::from([
+            ("LC_ALL".to_owned(), String::new()),
+            ("LC_CTYPE".to_owned(), "de_DE.ISO-8859-1".to_owned()),
+        ]));
+        assert_eq!(tp.process.output_encoding(), Encoding::Other);
+
+        assert_eq!(
+            TestProcess::default().process.output_encoding(),
+            Encoding::Other
+        );
+    }
+
+    #[test]
+    fn test_process_stdout_stderr_same_target() {
+        assert!(!TestProcess::default().process.stdout_stderr_same_target());
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TEST_SAME_OUTPUT".to_owned(),
+            "1".to_owned(),
+        )]));
+        assert!(tp.process.stdout_stderr_same_target());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_target_compares_files() {
+        use std::os::fd::AsFd;
+
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let log = dir.path().join("log");
+        let stdout = fs::File::create(&log).unwrap();
+        let stderr = fs::OpenOptions::new().append(true).open(&log).unwrap();
+        assert!(same_target(stdout.as_fd(), stderr.as_fd()));
+        assert!(same_target(stdout.as_fd(), stdout.as_fd()));
+
+        let other = fs::File::create(dir.path().join("other")).unwrap();
+        assert!(!same_target(stdout.as_fd(), other.as_fd()));
+    }
+
+    #[test]
+    fn test_process_terminal_width() {
+        assert_eq!(TestProcess::default().process.terminal_width(), 80);
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TERM_WIDTH".to_owned(),
+            "132".to_owned(),
+        )]));
+        assert_eq!(tp.process.terminal_width(), 132);
+
+        for value in ["wide", "0", "-1"] {
+            let tp = TestProcess::with_vars(HashMap::from([(
+                "RUSTUP_TERM_WIDTH".to_owned(),
+                value.to_owned(),
+            )]));
+            assert_eq!(tp.process.terminal_width(), 80);
+            assert_eq!(
+                tp.captured_logs(),
+                [(
+                    NotificationLevel::Warn,
+                    format!("ignoring invalid RUSTUP_TERM_WIDTH value '{value}'")
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_id() {
+        let a = TestProcess::default();
+        let b = TestProcess::default();
+        assert_ne!(a.process.id(), b.process.id());
+        assert_eq!(a.process.id(), a.process.clone().id());
+    }
+
+    #[test]
+    fn var_bool() {
+        let truthy = ["1", "true", "TRUE", "Yes", "on"];
+        let falsy = ["0", "false", "no", "off", "", "2", "y"];
+
+        let mut vars = HashMap::new();
+        for (i, v) in truthy.iter().chain(&falsy).enumerate() {
+            vars.insert(format!("VAR_{i}"), v.to_string());
+        }
+        let tp = TestProcess::with_vars(vars);
+        for i in 0..truthy.len() + falsy.len() {
+            assert_eq!(tp.process.var_bool(&format!("VAR_{i}")), i < truthy.len());
+        }
+        assert!(!tp.process.var_bool("VAR_UNSET"));
+
+        let process = Process::os();
+        let key = "RUSTUP_TEST_VAR_BOOL";
+        for v in truthy {
+            env::set_var(key, v);
+            assert!(process.var_bool(key), "{v}");
+        }
+        for v in falsy {
+            env::set_var(key, v);
+            assert!(!process.var_bool(key), "{v}");
+        }
+        env::remove_var(key);
+        assert!(!process.var_bool(key));
+    }
+
+    #[test]
+    fn test_process_is_elevated() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TEST_ELEVATED".to_owned(),
+            "1".to_owned(),
+        )]));
+        assert!(tp.process.is_elevated());
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TEST_ELEVATED".to_owned(),
+            "0".to_owned(),
+        )]));
+        assert!(!tp.process.is_elevated());
+
+        // The real uid and `SUDO_USER` don't leak into test processes.
+        assert!(!TestProcess::default().process.is_elevated());
+    }
+
+    #[test]
+    fn detect_shell() {
+        let tp =
+            TestProcess::with_vars(HashMap::from([("SHELL".to_owned(), "/bin/zsh".to_owned())]));
+        assert_eq!(tp.process.detect_shell(), Some(Shell::Zsh));
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "PSModulePath".to_owned(),
+            r"C:\Program Files\WindowsPowerShell\Modules".to_owned(),
+        )]));
+        assert_eq!(tp.process.detect_shell(), Some(Shell::PowerShell));
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "SHELL".to_owned(),
+            "/usr/bin/tcsh".to_owned(),
+        )]));
+        assert_eq!(tp.process.detect_shell(), None);
+        assert_eq!(TestProcess::default().process.detect_shell(), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn os_process_is_elevated_unix() {
+        let root = unsafe { libc::geteuid() } == 0;
+        let sudo = env::var_os("SUDO_USER").is_some();
+        assert_eq!(Process::os().is_elevated(), root || sudo);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn path_entries_unix() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "PATH".to_owned(),
+            "/usr/local/bin::/usr/bin:".to_owned(),
+        )]));
+        assert_eq!(
+            tp.process.path_entries(),
+            [PathBuf::from("/usr/local/bin"), PathBuf::from("/usr/bin")]
+        );
+
+        assert!(TestProcess::default().process.path_entries().is_empty());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn path_entries_windows() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "PATH".to_owned(),
+            r#"C:\Windows;;"C:\Program Files\a;b";C:\bin"#.to_owned(),
+        )]));
+        assert_eq!(
+            tp.process.path_entries(),
+            [
+                PathBuf::from(r"C:\Windows"),
+                PathBuf::from(r"C:\Program Files\a;b"),
+                PathBuf::from(r"C:\bin"),
+            ]
+        );
+
+        assert!(TestProcess::default().process.path_entries().is_empty());
+    }
+
+    #[test]
+    fn test_process_clock() {
+        let tp = TestProcess::default();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        tp.set_time(time);
+        assert_eq!(tp.process.now(), time);
+        assert_eq!(tp.process.clone().now(), time);
+
+        tp.advance_time(Duration::from_secs(5));
+        assert_eq!(tp.process.now(), time + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_process_line_buffering() {
+        use std::io::Write;
+
+        let tp = TestProcess::with_line_buffering();
+        let mut stdout = tp.process.stdout();
+        write!(stdout, "partial").unwrap();
+        assert!(tp.stdout().is_empty());
+        stdout.flush_all().unwrap();
+        assert_eq!(tp.stdout(), b"partial");
+    }
+
+    #[test]
+    fn test_process_stdin_timing_out() {
+        let tp = TestProcess::with_stdin_timing_out();
+        let mut buf = String::new();
+        let timeout = Duration::from_secs(3600);
+        let read = tp.process.stdin().read_line_timeout(&mut buf, timeout);
+        assert_eq!(read.unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_process_captured_logs() {
+        let tp = TestProcess::default();
+        tracing::warn!("something looks off");
+        tracing::debug!(answer = 42, "with fields");
+        assert_eq!(
+            tp.captured_logs(),
+            [
+                (NotificationLevel::Warn, "something looks off".to_owned()),
+                (NotificationLevel::Debug, "with fields".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_get_stderr_plain() {
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_TERM_COLOR".to_owned(),
+            "always".to_owned(),
+        )]));
+        tracing::warn!("colorful");
+        tracing::info!("plain");
+        assert!(tp.stderr().contains(&0x1b));
+        assert_eq!(tp.get_stderr_plain(), "warn: colorful\ninfo: plain\n");
+    }
+
+    #[test]
+    fn strip_ansi_sequences() {
+        let colored = b"\x1b[0m\x1b[1m\x1b[33mwarn: \x1b[0mhello \x1b[38;5;208mworld\x1b[0m\n";
+        assert_eq!(crate::test::strip_ansi(colored), "warn: hello world\n");
+        assert_eq!(crate::test::strip_ansi(b"no escapes"), "no escapes");
+    }
 }
@@ -1,4 +1,9 @@
 use std::io::{self, BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
 
 use super::terminalsource::{ColorableTerminal, StreamSelector};
 use crate::currentprocess::Process;
@@ -6,6 +11,43 @@ use crate::currentprocess::Process;
 /// Stand-in for std::io::Stdin
 pub trait Stdin {
     fn lock(&self) -> Box<dyn StdinLock + '_>;
+
+    /// Reads all remaining bytes from the stream into `buf`, without any
+    /// line or UTF-8 processing.
+    fn read_to_end(&self, buf: &mut Vec<u8>) -> io::Result<usize>;
+
+    /// Like [`BufRead::read_line`], but gives up and returns `None` if no
+    /// line arrives within `timeout`, so that a prompt can't hang forever
+    /// when nobody is there to answer it.
+    fn read_line_timeout(&self, buf: &mut String, timeout: Duration) -> io::Result<Option<usize>>;
+
+    /// Reads exactly `n` lines, with their `\n` or `\r\n` terminators
+    /// removed.
+    ///
+    /// A final line without a terminator still counts as a line. Fails with
+    /// [`io::ErrorKind::UnexpectedEof`] if the stream ends before `n` lines
+    /// could be read; the lines read so far are consumed either way.
+    fn read_lines(&self, n: usize) -> io::Result<Vec<String>> {
+        let mut lock = self.lock();
+        let mut lines = Vec::with_capacity(n);
+        for i in 0..n {
+            let mut line = String::new();
+            if lock.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("expected {n} lines, got {i}"),
+                ));
+            }
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            lines.push(line);
+        }
+        Ok(lines)
+    }
 }
 
 /// Stand-in for std::io::StdinLock
@@ -19,6 +61,66 @@ impl Stdin for io::Stdin {
     fn lock(&self) -> Box<dyn StdinLock + '_> {
         Box::new(io::Stdin::lock(self))
     }
+
+    fn read_to_end(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        io::Stdin::lock(self).read_to_end(buf)
+    }
+
+    /// Stdin can't be read with a timeout portably, so lines are read by a
+    /// single background thread, one per request. After a timeout that
+    /// thread keeps waiting, and the next call picks up its line instead of
+    /// requesting another one.
+    fn read_line_timeout(&self, buf: &mut String, timeout: Duration) -> io::Result<Option<usize>> {
+        struct LineReader {
+            requests: mpsc::Sender<()>,
+            lines: mpsc::Receiver<io::Result<String>>,
+            pending: bool,
+        }
+        static READER: Mutex<Option<LineReader>> = Mutex::new(None);
+
+        let stopped = || io::Error::new(io::ErrorKind::Other, "the thread reading stdin stopped");
+        let mut guard = READER.lock().unwrap_or_else(|e| e.into_inner());
+        let reader = guard.get_or_insert_with(|| {
+            let (requests, rx) = mpsc::channel();
+            let (tx, lines) = mpsc::channel();
+            thread::spawn(move || {
+                // Only read when asked to, so that stdin is left alone while
+                // nobody is waiting for a line
+                for () in rx {
+                    let mut line = String::new();
+                    let result = io::stdin().read_line(&mut line).map(|_| line);
+                    if tx.send(result).is_err() {
+                        break;
+                    }
+                }
+            });
+            LineReader {
+                requests,
+                lines,
+                pending: false,
+            }
+        });
+        if !reader.pending {
+            if reader.requests.send(()).is_err() {
+                *guard = None;
+                return Err(stopped());
+            }
+            reader.pending = true;
+        }
+        match reader.lines.recv_timeout(timeout) {
+            Ok(result) => {
+                reader.pending = false;
+                let line = result?;
+                buf.push_str(&line);
+                Ok(Some(line.len()))
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                *guard = None;
+                Err(stopped())
+            }
+        }
+    }
 }
 
 // -------------- stdout -------------------------------
@@ -39,6 +141,13 @@ pub trait Writer: Write + Send + Sync {
 
     /// Construct a terminal on this writer.
     fn terminal(&self, process: &Process) -> ColorableTerminal;
+
+    /// Lock the stream and flush any buffered output to its destination.
+    ///
+    /// Call this before the process exits to make sure nothing is lost.
+    /// In-memory test writers only have a pending partial line to flush,
+    /// if they are line buffered.
+    fn flush_all(&self) -> io::Result<()>;
 }
 
 // ----------------- OS support for writers -----------------
@@ -61,6 +170,10 @@ impl Writer for io::Stdout {
     fn terminal(&self, process: &Process) -> ColorableTerminal {
         ColorableTerminal::new(StreamSelector::Stdout, process)
     }
+
+    fn flush_all(&self) -> io::Result<()> {
+        io::Stdout::lock(self).flush()
+    }
 }
 
 impl WriterLock for io::StderrLock<'_> {}
@@ -81,6 +194,223 @@ impl Writer for io::Stderr {
     fn terminal(&self, process: &Process) -> ColorableTerminal {
         ColorableTerminal::new(StreamSelector::Stderr, process)
     }
+
+    fn flush_all(&self) -> io::Result<()> {
+        io::Stderr::lock(self).flush()
+    }
+}
+
+// ----------------- tee support for writers -----------------
+
+/// A [`Writer`] that forwards everything written to a primary writer (e.g.
+/// the process' stderr) to a secondary sink (e.g. a log file) as well.
+///
+/// TTY detection and terminal construction only ever consider the primary.
+/// The secondary is best-effort: errors writing to it are ignored, so that a
+/// full disk can't break the primary output.
+pub(crate) struct TeeWriter {
+    primary: Box<dyn Writer>,
+    secondary: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl TeeWriter {
+    pub(crate) fn new(primary: Box<dyn Writer>, secondary: Arc<Mutex<dyn Write + Send>>) -> Self {
+        Self { primary, secondary }
+    }
+
+    fn lock_secondary(&self) -> MutexGuard<'_, dyn Write + Send + 'static> {
+        // The sink can be locked even if another thread panicked mid-write:
+        // at worst the log file misses part of a line.
+        self.secondary.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+struct TeeWriterLock<'a> {
+    primary: Box<dyn WriterLock + 'a>,
+    secondary: MutexGuard<'a, dyn Write + Send + 'static>,
+}
+
+impl WriterLock for TeeWriterLock<'_> {}
+
+impl Write for TeeWriterLock<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.primary.write(buf)?;
+        let _ = self.secondary.write_all(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.primary.flush()?;
+        let _ = self.secondary.flush();
+        Ok(())
+    }
+}
+
+impl Writer for TeeWriter {
+    fn is_a_tty(&self, process: &Process) -> bool {
+        self.primary.is_a_tty(process)
+    }
+
+    fn lock(&self) -> Box<dyn WriterLock + '_> {
+        Box::new(TeeWriterLock {
+            primary: self.primary.lock(),
+            secondary: self.lock_secondary(),
+        })
+    }
+
+    fn terminal(&self, process: &Process) -> ColorableTerminal {
+        self.primary.terminal(process)
+    }
+
+    fn flush_all(&self) -> io::Result<()> {
+        self.primary.flush_all()?;
+        let _ = self.lock_secondary().flush();
+        Ok(())
+    }
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Writer::lock(self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Writer::lock(self).flush()
+    }
+}
+
+/// A [`Write`] adapter that drops ANSI escape sequences, so that colored
+/// terminal output can be mirrored into a plain-text log file.
+///
+/// Sequences split across writes are tracked, so they are dropped as well.
+#[derive(Debug)]
+pub(crate) struct StripAnsi<W> {
+    inner: W,
+    state: AnsiState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AnsiState {
+    Text,
+    /// After an `ESC`.
+    Escape,
+    /// Inside an `ESC [` control sequence, up to its final byte in `@..=~`.
+    Control,
+}
+
+impl<W: Write> StripAnsi<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: AnsiState::Text,
+        }
+    }
+}
+
+impl<W: Write> Write for StripAnsi<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut plain = Vec::with_capacity(buf.len());
+        for &b in buf {
+            self.state = match (self.state, b) {
+                (AnsiState::Text, 0x1b) => AnsiState::Escape,
+                (AnsiState::Text, b) => {
+                    plain.push(b);
+                    AnsiState::Text
+                }
+                (AnsiState::Escape, b'[') => AnsiState::Control,
+                (AnsiState::Escape, _) => AnsiState::Text,
+                (AnsiState::Control, 0x40..=0x7e) => AnsiState::Text,
+                (AnsiState::Control, _) => AnsiState::Control,
+            };
+        }
+        self.inner.write_all(&plain)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ----------------- line prefixing for writers -----------------
+
+/// A [`Writer`] that tags every line written to it with a prefix, e.g.
+/// `[rustc] `, before passing it on to the wrapped writer.
+///
+/// Whether the next write starts a new line is tracked across writes and
+/// locks, so lines written in several pieces are still prefixed only once.
+pub struct PrefixWriter {
+    inner: Box<dyn Writer>,
+    prefix: Vec<u8>,
+    at_line_start: AtomicBool,
+}
+
+impl PrefixWriter {
+    pub fn new(inner: Box<dyn Writer>, prefix: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+            at_line_start: AtomicBool::new(true),
+        }
+    }
+}
+
+struct PrefixWriterLock<'a> {
+    inner: Box<dyn WriterLock + 'a>,
+    prefix: &'a [u8],
+    at_line_start: &'a AtomicBool,
+}
+
+impl WriterLock for PrefixWriterLock<'_> {}
+
+impl Write for PrefixWriterLock<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start.load(Ordering::Relaxed) {
+                self.inner.write_all(self.prefix)?;
+            }
+            self.inner.write_all(line)?;
+            self.at_line_start
+                .store(line.ends_with(b"\n"), Ordering::Relaxed);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Writer for PrefixWriter {
+    fn is_a_tty(&self, process: &Process) -> bool {
+        self.inner.is_a_tty(process)
+    }
+
+    fn lock(&self) -> Box<dyn WriterLock + '_> {
+        Box::new(PrefixWriterLock {
+            inner: self.inner.lock(),
+            prefix: &self.prefix,
+            at_line_start: &self.at_line_start,
+        })
+    }
+
+    fn terminal(&self, process: &Process) -> ColorableTerminal {
+        self.inner.terminal(process)
+    }
+
+    fn flush_all(&self) -> io::Result<()> {
+        self.inner.flush_all()
+    }
+}
+
+impl Write for PrefixWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Writer::lock(self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Writer::lock(self).flush()
+    }
 }
 
 #[cfg(feature = "test")]
@@ -98,7 +428,7 @@ mod test_support {
     // ----------------------- test support for stdin ------------------
 
     struct TestStdinLock<'a> {
-        inner: MutexGuard<'a, Cursor<String>>,
+        inner: MutexGuard<'a, Cursor<Vec<u8>>>,
     }
 
     impl StdinLock for TestStdinLock<'_> {}
@@ -118,29 +448,69 @@ mod test_support {
         }
     }
 
-    pub(crate) type TestStdinInner = Arc<Mutex<Cursor<String>>>;
+    pub(crate) type TestStdinInner = Arc<Mutex<Cursor<Vec<u8>>>>;
 
-    pub struct TestStdin(pub(in super::super) TestStdinInner);
+    pub struct TestStdin {
+        inner: TestStdinInner,
+        timeout: bool,
+    }
+
+    impl TestStdin {
+        pub(crate) fn new(inner: TestStdinInner) -> Self {
+            Self {
+                inner,
+                timeout: false,
+            }
+        }
+
+        /// Sets whether [`Stdin::read_line_timeout`] times out right away, as
+        /// if nobody answered.
+        pub(crate) fn timing_out(mut self, enabled: bool) -> Self {
+            self.timeout = enabled;
+            self
+        }
+    }
 
     impl Stdin for TestStdin {
         fn lock(&self) -> Box<dyn StdinLock + '_> {
             Box::new(TestStdinLock {
-                inner: self.0.lock().unwrap_or_else(|e| e.into_inner()),
+                inner: self.inner.lock().unwrap_or_else(|e| e.into_inner()),
             })
         }
+
+        fn read_to_end(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+            self.inner
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .read_to_end(buf)
+        }
+
+        /// Reads from the buffer right away, never waiting.
+        fn read_line_timeout(&self, buf: &mut String, _: Duration) -> io::Result<Option<usize>> {
+            if self.timeout {
+                return Ok(None);
+            }
+            self.lock().read_line(buf).map(Some)
+        }
     }
 
     // ----------------------- test support for writers ------------------
 
     pub(in super::super) struct TestWriterLock<'a> {
         inner: MutexGuard<'a, Vec<u8>>,
+        cap: Option<usize>,
     }
 
     impl WriterLock for TestWriterLock<'_> {}
 
     impl Write for TestWriterLock<'_> {
         fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            self.inner.write(buf)
+            let written = self.inner.write(buf)?;
+            if let Some(cap) = self.cap {
+                let excess = self.inner.len().saturating_sub(cap);
+                self.inner.drain(..excess);
+            }
+            Ok(written)
         }
 
         fn flush(&mut self) -> io::Result<()> {
@@ -151,19 +521,69 @@ mod test_support {
     pub(in super::super) type TestWriterInner = Arc<Mutex<Vec<u8>>>;
 
     /// A thread-safe test file handle that pretends to be e.g. stdout.
-    #[derive(Clone, Default)]
-    pub(in super::super) struct TestWriter(pub(in super::super) TestWriterInner);
+    ///
+    /// In line-buffered mode, bytes written through the handle are held back
+    /// until a newline, so lines written concurrently through different
+    /// clones never interleave. Each clone has its own pending line, which
+    /// is flushed when the clone is dropped or by [`Writer::flush_all`];
+    /// tests asserting on output must make sure that happens first.
+    ///
+    /// With a cap, only the last `cap` bytes of the output are retained, which
+    /// keeps memory bounded for tests that produce a lot of it.
+    #[derive(Default)]
+    pub(in super::super) struct TestWriter {
+        pub(in super::super) inner: TestWriterInner,
+        line_buffer: Option<Mutex<Vec<u8>>>,
+        cap: Option<usize>,
+    }
 
     impl TestWriter {
+        pub(in super::super) fn new(inner: TestWriterInner) -> Self {
+            Self {
+                inner,
+                line_buffer: None,
+                cap: None,
+            }
+        }
+
+        /// Limits the retained output to its last `cap` bytes, if set.
+        pub(in super::super) fn with_cap(mut self, cap: Option<usize>) -> Self {
+            self.cap = cap;
+            self
+        }
+
+        /// Turns line buffering on or off. Unbuffered is the default.
+        pub(in super::super) fn line_buffered(mut self, enabled: bool) -> Self {
+            self.line_buffer = enabled.then(Mutex::default);
+            self
+        }
+
         pub(in super::super) fn lock(&self) -> TestWriterLock<'_> {
             // The stream can be locked even if a test thread panicked: its state
             // will be ok
             TestWriterLock {
-                inner: self.0.lock().unwrap_or_else(|e| e.into_inner()),
+                inner: self.inner.lock().unwrap_or_else(|e| e.into_inner()),
+                cap: self.cap,
+            }
+        }
+    }
+
+    impl Clone for TestWriter {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+                line_buffer: self.line_buffer.as_ref().map(|_| Mutex::default()),
+                cap: self.cap,
             }
         }
     }
 
+    impl Drop for TestWriter {
+        fn drop(&mut self) {
+            let _ = self.flush_all();
+        }
+    }
+
     impl Writer for TestWriter {
         fn is_a_tty(&self, _: &Process) -> bool {
             false
@@ -176,15 +596,221 @@ mod test_support {
         fn terminal(&self, process: &Process) -> ColorableTerminal {
             ColorableTerminal::new(StreamSelector::TestWriter(self.clone()), process)
         }
+
+        fn flush_all(&self) -> io::Result<()> {
+            if let Some(pending) = &self.line_buffer {
+                let mut pending = pending.lock().unwrap_or_else(|e| e.into_inner());
+                let partial = std::mem::take(&mut *pending);
+                self.lock().write_all(&partial)?;
+            }
+            Ok(())
+        }
     }
 
     impl Write for TestWriter {
         fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            self.lock().write(buf)
+            let Some(pending) = &mut self.line_buffer else {
+                return self.lock().write(buf);
+            };
+            let pending = pending.get_mut().unwrap_or_else(|e| e.into_inner());
+            pending.extend_from_slice(buf);
+            if let Some(end) = pending.iter().rposition(|&b| b == b'\n') {
+                let lines = pending.drain(..=end).collect::<Vec<_>>();
+                self.lock().write_all(&lines)?;
+            }
+            Ok(buf.len())
         }
 
         fn flush(&mut self) -> io::Result<()> {
-            Ok(())
+            self.flush_all()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_stdin_read_to_end_binary() {
+        let bytes = vec![0x00, 0xff, 0xfe, b'\n', 0x80, b'\r', 0x7f];
+        let stdin = TestStdin::new(Arc::new(Mutex::new(Cursor::new(bytes.clone()))));
+
+        let mut buf = Vec::new();
+        assert_eq!(stdin.read_to_end(&mut buf).unwrap(), bytes.len());
+        assert_eq!(buf, bytes);
+
+        // The stream is now exhausted.
+        buf.clear();
+        assert_eq!(stdin.read_to_end(&mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn tee_writer_forwards_to_both() {
+        let primary = TestWriter::default();
+        let secondary = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let mut tee = TeeWriter::new(Box::new(primary.clone()), secondary.clone());
+
+        tee.write_all(b"info: syncing channel updates\n").unwrap();
+        writeln!(Writer::lock(&tee), "warn: {}", 42).unwrap();
+        tee.flush().unwrap();
+
+        let expected = b"info: syncing channel updates\nwarn: 42\n";
+        assert_eq!(&*primary.inner.lock().unwrap(), expected);
+        assert_eq!(&*secondary.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn tee_writer_ignores_secondary_errors() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "no space left"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::Error::new(io::ErrorKind::Other, "no space left"))
+            }
+        }
+
+        let primary = TestWriter::default();
+        let mut tee = TeeWriter::new(Box::new(primary.clone()), Arc::new(Mutex::new(Full)));
+
+        writeln!(tee, "info: syncing channel updates").unwrap();
+        tee.flush().unwrap();
+        tee.flush_all().unwrap();
+
+        assert_eq!(
+            &*primary.inner.lock().unwrap(),
+            b"info: syncing channel updates\n"
+        );
+    }
+
+    #[test]
+    fn strip_ansi_drops_escape_sequences() {
+        let mut writer = StripAnsi::new(Vec::new());
+        writer
+            .write_all(b"\x1b[0m\x1b[1m\x1b[33mwarn: \x1b[0m")
+            .unwrap();
+        // A sequence split across writes.
+        writer.write_all(b"skipping\n\x1b[1").unwrap();
+        writer.write_all(b";31merror: \x1b").unwrap();
+        writer.write_all(b"[0mfailed\n").unwrap();
+
+        assert_eq!(writer.inner, b"warn: skipping\nerror: failed\n");
+    }
+
+    #[test]
+    fn prefix_writer_prefixes_each_line_once() {
+        let inner = TestWriter::default();
+        let mut writer = PrefixWriter::new(Box::new(inner.clone()), "[rustc] ");
+
+        writer.write_all(b"Compiling foo\nCompil").unwrap();
+        writer.write_all(b"ing bar\n\nFinished").unwrap();
+        writeln!(Writer::lock(&writer), " in {}s", 2).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(
+            String::from_utf8(inner.inner.lock().unwrap().clone()).unwrap(),
+            "[rustc] Compiling foo\n[rustc] Compiling bar\n[rustc] \n[rustc] Finished in 2s\n"
+        );
+    }
+
+    #[test]
+    fn test_stdin_read_lines() {
+        let input = b"yes\r\n2\nstable".to_vec();
+        let stdin = TestStdin::new(Arc::new(Mutex::new(Cursor::new(input))));
+        assert_eq!(stdin.read_lines(3).unwrap(), ["yes", "2", "stable"]);
+        assert_eq!(stdin.read_lines(0).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_stdin_read_lines_eof() {
+        let input = b"yes\n2\n".to_vec();
+        let stdin = TestStdin::new(Arc::new(Mutex::new(Cursor::new(input))));
+        let err = stdin.read_lines(3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_stdin_read_line_timeout() {
+        let input = b"y\nn\n".to_vec();
+        let stdin = TestStdin::new(Arc::new(Mutex::new(Cursor::new(input))));
+        let mut buf = String::new();
+        let timeout = Duration::from_secs(3600);
+        assert_eq!(stdin.read_line_timeout(&mut buf, timeout).unwrap(), Some(2));
+        assert_eq!(buf, "y\n");
+
+        let input = b"y\n".to_vec();
+        let stdin = TestStdin::new(Arc::new(Mutex::new(Cursor::new(input)))).timing_out(true);
+        let mut buf = String::new();
+        assert_eq!(stdin.read_line_timeout(&mut buf, timeout).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_writer_line_buffered_flushes_on_drop() {
+        let writer = TestWriter::default().line_buffered(true);
+
+        let mut clone = writer.clone();
+        write!(clone, "no newline").unwrap();
+        assert!(writer.inner.lock().unwrap().is_empty());
+        drop(clone);
+        assert_eq!(&*writer.inner.lock().unwrap(), b"no newline");
+
+        let mut clone = writer.clone();
+        write!(clone, ", still none").unwrap();
+        clone.flush_all().unwrap();
+        assert_eq!(&*writer.inner.lock().unwrap(), b"no newline, still none");
+    }
+
+    #[test]
+    fn test_writer_cap() {
+        let mut writer = TestWriter::default().with_cap(Some(8));
+        writer.write_all(b"0123456789").unwrap();
+        assert_eq!(&*writer.inner.lock().unwrap(), b"23456789");
+
+        writeln!(writer, "abc").unwrap();
+        assert_eq!(&*writer.inner.lock().unwrap(), b"6789abc\n");
+    }
+
+    #[test]
+    fn test_writer_flush_all() {
+        let mut writer = TestWriter::default().line_buffered(true);
+        write!(writer, "complete\nunflushed").unwrap();
+        assert_eq!(&*writer.inner.lock().unwrap(), b"complete\n");
+        writer.flush_all().unwrap();
+        assert_eq!(&*writer.inner.lock().unwrap(), b"complete\nunflushed");
+    }
+
+    #[test]
+    fn test_writer_line_buffered() {
+        let writer = TestWriter::default().line_buffered(true);
+        let threads = (0..2)
+            .map(|i| {
+                let mut writer = writer.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        // Each line is written in two halves.
+                        write!(writer, "thread {i} ").unwrap();
+                        writeln!(writer, "line").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let output = String::from_utf8(writer.inner.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 200);
+        assert!(lines
+            .iter()
+            .all(|l| *l == "thread 0 line" || *l == "thread 1 line"));
+    }
+}
@@ -2,28 +2,70 @@
 //! `Components` and `DirectoryPackage` are the two sides of the
 //! installation / uninstallation process.
 
-use std::io::BufWriter;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::currentprocess::Process;
 use crate::dist::component::package::{INSTALLER_VERSION, VERSION_FILE};
 use crate::dist::component::transaction::Transaction;
+use crate::dist::manifest::Manifest;
+use crate::dist::manifestation::{DIST_MANIFEST, INSTALL_SUMMARY_TARGET};
+use crate::dist::notifications::*;
 use crate::dist::prefix::InstallPrefix;
+use crate::dist::triple::is_known_triple;
 use crate::errors::RustupError;
+use crate::utils::raw;
 use crate::utils::utils;
 
 const COMPONENTS_FILE: &str = "components";
+const LOCK_FILE: &str = ".components.lock";
+/// The components [`Components::install_many`] installs first, most important
+/// first, so that an interrupted batch is more likely to leave a usable
+/// toolchain behind.
+const INSTALL_PRIORITY: &[&str] = &["rustc", "rust-std", "cargo"];
 
 #[derive(Clone, Debug)]
 pub struct Components {
     prefix: InstallPrefix,
+    sorted: bool,
+    read_only: bool,
+    components_file: String,
+    lock_file: Option<PathBuf>,
 }
 
 impl Components {
     pub fn open(prefix: InstallPrefix) -> Result<Self> {
-        let c = Self { prefix };
+        Self::open_with_filename(prefix, COMPONENTS_FILE)
+    }
+    /// Like [`Components::open`], but keeps the list of installed components
+    /// in the manifest file `name` instead of `components`.
+    ///
+    /// The version file and the component manifests are shared with the
+    /// default list.
+    pub fn open_with_filename(prefix: InstallPrefix, name: &str) -> Result<Self> {
+        Self::open_inner(prefix, name, false)
+    }
+    fn open_inner(prefix: InstallPrefix, name: &str, read_only: bool) -> Result<Self> {
+        if name.is_empty() || name.contains(['/', '\\']) {
+            bail!("invalid components file name: {name:?}");
+        }
+        let c = Self {
+            prefix,
+            sorted: false,
+            read_only,
+            components_file: name.to_owned(),
+            lock_file: None,
+        };
 
         // Validate that the metadata uses a format we know
         if let Some(v) = c.read_version()? {
@@ -35,13 +77,173 @@ impl Components {
             }
         }
 
+        if !read_only {
+            c.recover_staged_list()?;
+        }
+
         Ok(c)
     }
+    /// Like [`Components::open`], but first checks that the prefix is an
+    /// existing directory and that its manifest directory, if present, can
+    /// be read, so that a mistyped prefix is reported immediately.
+    pub fn open_checked(prefix: InstallPrefix) -> Result<Self> {
+        if !utils::is_directory(prefix.path()) {
+            return Err(RustupError::InstallPrefixNotFound(prefix.path().to_path_buf()).into());
+        }
+        let manifest_dir = prefix.manifest_dir();
+        if utils::path_exists(&manifest_dir) {
+            utils::read_dir("manifest", &manifest_dir)?;
+        }
+        Self::open(prefix)
+    }
+    /// Opens the components of the toolchain this process was run from, for
+    /// tools proxied by rustup: the toolchain that `RUSTUP_TOOLCHAIN` names
+    /// in `$RUSTUP_HOME/toolchains`, or the one at that path for custom
+    /// toolchains.
+    pub fn for_current_toolchain(process: &Process) -> Result<Self> {
+        let name = process
+            .var_os("RUSTUP_TOOLCHAIN")
+            .ok_or(RustupError::NoCurrentToolchain)?;
+        let path = PathBuf::from(&name);
+        let prefix = if path.is_absolute() {
+            path
+        } else {
+            process.rustup_home()?.join("toolchains").join(name)
+        };
+        Self::open_checked(InstallPrefix::from(prefix))
+    }
+    /// Like [`Components::open`], but every operation that would modify the
+    /// installation fails up front with [`RustupError::ComponentsReadOnly`].
+    ///
+    /// This is meant for inspecting toolchains on shared or immutable
+    /// storage without risking a partial write.
+    pub fn open_read_only(prefix: InstallPrefix) -> Result<Self> {
+        Self::open_inner(prefix, COMPONENTS_FILE, true)
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    fn check_mutable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(RustupError::ComponentsReadOnly {
+                path: self.prefix.path().to_path_buf(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+    /// When `sorted` is set, newly added components are inserted into the
+    /// `components` file in name order instead of being appended, so that
+    /// its contents don't depend on the order of installation.
+    pub fn with_sorted_list(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+    /// Locks `path` instead of `.components.lock` in the manifest directory
+    /// while modifying the installation.
+    pub fn with_lock_file(mut self, path: PathBuf) -> Self {
+        self.lock_file = Some(path);
+        self
+    }
+    /// Takes the lock that serializes modifications of the installation
+    /// between processes, unless `tx` already holds it, waiting for it if
+    /// need be. The lock is released along with the transaction.
+    fn lock(&self, tx: &mut Transaction<'_>) -> Result<()> {
+        if tx.is_dry_run() {
+            return Ok(());
+        }
+        let path = self.lock_path();
+        if tx.holds_lock(&path) {
+            return Ok(());
+        }
+        let file = Self::open_lock_file(&path)?;
+        let lock_err = || format!("could not lock '{}'", path.display());
+        if !raw::lock_exclusive(&file, false).with_context(lock_err)? {
+            (tx.notify_handler())(Notification::WaitingForComponentsLock(&path));
+            raw::lock_exclusive(&file, true).with_context(lock_err)?;
+        }
+        tx.hold_lock(path, file);
+        Ok(())
+    }
+    fn lock_path(&self) -> PathBuf {
+        match &self.lock_file {
+            Some(path) => path.clone(),
+            None => self.prefix.manifest_file(LOCK_FILE),
+        }
+    }
+    fn open_lock_file(path: &Path) -> Result<fs::File> {
+        if let Some(parent) = path.parent() {
+            utils::ensure_dir_exists("lock", parent, &|_: Notification<'_>| ())?;
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("could not open lock file '{}'", path.display()))
+    }
     fn rel_components_file(&self) -> PathBuf {
-        self.prefix.rel_manifest_file(COMPONENTS_FILE)
+        self.prefix.rel_manifest_file(&self.components_file)
+    }
+    /// Where uninstallation writes the new `components` file before renaming
+    /// it into place. It lives next to the real one so that the rename is
+    /// atomic.
+    fn rel_staged_components_file(&self) -> PathBuf {
+        self.prefix
+            .rel_manifest_file(&format!("{}.new", self.components_file))
+    }
+    /// Cleans up after an uninstallation that was interrupted while
+    /// rewriting the `components` file.
+    ///
+    /// A staged file next to an existing `components` file means the rename
+    /// never happened, so the old list still holds and the staged one is
+    /// discarded. If the `components` file is gone, the staged one is the
+    /// only list left and is moved into place.
+    ///
+    /// Nothing is done while another uninstallation holds the lock, as it
+    /// may be staging the list right now; a later open recovers instead.
+    fn recover_staged_list(&self) -> Result<()> {
+        let staged = self.prefix.abs_path(self.rel_staged_components_file());
+        if !utils::is_file(&staged) {
+            return Ok(());
+        }
+        let lock_path = self.lock_path();
+        // Held until the recovery is done
+        let lock = Self::open_lock_file(&lock_path)?;
+        let locked = raw::lock_exclusive(&lock, false)
+            .with_context(|| format!("could not lock '{}'", lock_path.display()))?;
+        // The uninstallation may also have finished before we got the lock
+        if !locked || !utils::is_file(&staged) {
+            return Ok(());
+        }
+        let path = self.prefix.abs_path(self.rel_components_file());
+        if utils::is_file(&path) {
+            utils::remove_file("components", &staged)
+        } else {
+            fs::rename(&staged, &path).with_context(|| {
+                format!(
+                    "could not restore the components file from '{}'",
+                    staged.display()
+                )
+            })
+        }
+    }
+    /// The manifest file of component `name`, relative to the prefix.
+    ///
+    /// Names read back from the `components` file aren't validated, so a
+    /// separator here is refused rather than letting the manifest escape
+    /// the manifest directory.
+    fn rel_component_manifest(&self, name: &str) -> Result<PathBuf> {
+        if name.contains(['/', '\\']) {
+            return Err(RustupError::ComponentManifestNameHasSeparator(name.to_owned()).into());
+        }
+        Ok(self.prefix.rel_manifest_file(&format!("manifest-{name}")))
     }
-    fn rel_component_manifest(&self, name: &str) -> PathBuf {
-        self.prefix.rel_manifest_file(&format!("manifest-{name}"))
+    /// The version rustup writes to, and expects in, the
+    /// `rust-installer-version` file, for tools that produce metadata
+    /// compatible with it.
+    pub fn expected_version() -> &'static str {
+        INSTALLER_VERSION
     }
     fn read_version(&self) -> Result<Option<String>> {
         let p = self.prefix.manifest_file(VERSION_FILE);
@@ -53,6 +255,9 @@ impl Components {
     }
     fn write_version(&self, tx: &mut Transaction<'_>) -> Result<()> {
         tx.modify_file(self.prefix.rel_manifest_file(VERSION_FILE))?;
+        if tx.is_dry_run() {
+            return Ok(());
+        }
         utils::write_file(
             VERSION_FILE,
             &self.prefix.manifest_file(VERSION_FILE),
@@ -61,7 +266,40 @@ impl Components {
 
         Ok(())
     }
+    /// Writes the version file if it is missing, e.g. to repair an
+    /// installation whose version file was deleted while components remain.
+    pub fn ensure_version<'a>(&self, mut tx: Transaction<'a>) -> Result<Transaction<'a>> {
+        self.check_mutable()?;
+        self.lock(&mut tx)?;
+        if self.read_version()?.is_none() {
+            self.write_version(&mut tx)?;
+        }
+        Ok(tx)
+    }
+    /// The version of the `rust` package recorded in the channel manifest
+    /// that was installed along with the components, read without running
+    /// `rustc`.
+    ///
+    /// Returns `None` if there is no such manifest, e.g. for toolchains not
+    /// installed from a channel.
+    pub fn rust_version(&self) -> Result<Option<String>> {
+        let path = self.prefix.manifest_file(DIST_MANIFEST);
+        if !utils::is_file(&path) {
+            return Ok(None);
+        }
+        let manifest = Manifest::parse(&utils::read_file("installed manifest", &path)?)
+            .with_context(|| RustupError::ParsingFile {
+                name: "manifest",
+                path,
+            })?;
+        Ok(Some(manifest.get_rust_version()?.to_owned()))
+    }
     pub fn list(&self) -> Result<Vec<Component>> {
+        self.list_filtered(|_| true)
+    }
+    /// Like [`Components::list`], but only builds a [`Component`] for the
+    /// names matching `pred`.
+    pub fn list_filtered(&self, pred: impl Fn(&str) -> bool) -> Result<Vec<Component>> {
         let path = self.prefix.abs_path(self.rel_components_file());
         if !utils::is_file(&path) {
             return Ok(Vec::new());
@@ -69,60 +307,827 @@ impl Components {
         let content = utils::read_file("components", &path)?;
         Ok(content
             .lines()
+            .map(|s| s.strip_suffix('\r').unwrap_or(s))
+            .filter(|s| !s.trim().is_empty() && pred(s))
             .map(|s| Component {
                 components: self.clone(),
                 name: s.to_owned(),
+                parts: Arc::default(),
             })
             .collect())
     }
-    pub(crate) fn add<'a>(&self, name: &str, tx: Transaction<'a>) -> ComponentBuilder<'a> {
-        ComponentBuilder {
+    /// The installed preview components, i.e. those whose name ends in
+    /// `-preview`.
+    pub fn previews(&self) -> Result<Vec<Component>> {
+        self.list_filtered(|name| name.ends_with("-preview"))
+    }
+    /// The installed components that aren't previews, complementing
+    /// [`Components::previews`].
+    pub fn non_previews(&self) -> Result<Vec<Component>> {
+        self.list_filtered(|name| !name.ends_with("-preview"))
+    }
+    /// Iterates over the installed components, reading the `components` file
+    /// a line at a time rather than loading all of it like
+    /// [`Components::list`] does.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<Component>> + '_> {
+        Ok(self.names()?.map(move |name| {
+            Ok(Component {
+                components: self.clone(),
+                name: name?,
+                parts: Arc::default(),
+            })
+        }))
+    }
+    /// The number of installed components, counted in bounded memory.
+    pub fn count(&self) -> Result<usize> {
+        let mut count = 0;
+        for name in self.names()? {
+            name?;
+            count += 1;
+        }
+        Ok(count)
+    }
+    /// Streams the names in the `components` file, skipping blank lines.
+    fn names(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        let path = self.prefix.abs_path(self.rel_components_file());
+        let file = if utils::is_file(&path) {
+            Some(
+                fs::File::open(&path).with_context(|| RustupError::ReadingFile {
+                    name: "components",
+                    path: path.clone(),
+                })?,
+            )
+        } else {
+            None
+        };
+        let lines = file
+            .map(|f| BufReader::new(f).lines())
+            .into_iter()
+            .flatten();
+        Ok(lines.filter_map(move |line| match line {
+            Ok(mut line) => {
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+                (!line.trim().is_empty()).then_some(Ok(line))
+            }
+            Err(e) => Some(Err(e).with_context(|| RustupError::ReadingFile {
+                name: "components",
+                path: path.clone(),
+            })),
+        }))
+    }
+    /// Starts recording a new component.
+    ///
+    /// The name ends up both as a line in the `components` file and in the
+    /// `manifest-<name>` file name, so it must be non-empty and must not
+    /// contain line breaks or path separators.
+    pub(crate) fn add<'a>(
+        &self,
+        name: &str,
+        mut tx: Transaction<'a>,
+    ) -> Result<ComponentBuilder<'a>> {
+        self.check_mutable()?;
+        validate_name(name)?;
+        if !tx.is_dry_run() {
+            self.check_writable()?;
+        }
+        self.lock(&mut tx)?;
+        Ok(ComponentBuilder {
             components: self.clone(),
             name: name.to_owned(),
             parts: Vec::new(),
+            summary: InstallSummary::default(),
+            count_bytes: tracing::enabled!(target: INSTALL_SUMMARY_TARGET, tracing::Level::INFO),
+            owners: None,
+            batch: Vec::new(),
             tx,
+        })
+    }
+    /// Installs several components in a single transaction: `fill` is called
+    /// with a [`ComponentBuilder`] for each name to add the component's
+    /// parts, then the `components` file and the version file are each
+    /// written once for the whole batch.
+    ///
+    /// The batch is installed in [`INSTALL_PRIORITY`] order, followed by the
+    /// components missing from it in their given order.
+    ///
+    /// A [`Notification::ComponentBatchProgress`] is emitted after each
+    /// component. If `cancel` is set, it is checked between components and
+    /// between file operations.
+    pub(crate) fn install_many<'a, N, F>(
+        &self,
+        batch: impl IntoIterator<Item = (N, F)>,
+        mut tx: Transaction<'a>,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(Transaction<'a>, InstallSummary)>
+    where
+        N: AsRef<str>,
+        F: FnOnce(&mut ComponentBuilder<'a>) -> Result<()>,
+    {
+        self.check_mutable()?;
+        self.lock(&mut tx)?;
+        let mut batch = batch.into_iter().collect::<Vec<_>>();
+        batch.sort_by_key(|(name, _)| install_priority(name.as_ref()));
+        let total = batch.len();
+        let mut names = self.list()?.into_iter().map(|c| c.name).collect::<Vec<_>>();
+        let mut summary = InstallSummary::default();
+        // Shared between the builders, so that batch members can't claim
+        // each other's paths
+        let mut owners = None;
+        let mut batch_parts = Vec::new();
+
+        for (done, (name, fill)) in batch.into_iter().enumerate() {
+            check_cancelled(cancel)?;
+            let mut builder = self.add(name.as_ref(), tx)?;
+            builder.owners = owners.take();
+            builder.batch = mem::take(&mut batch_parts);
+            fill(&mut builder)?;
+            owners = builder.owners.take();
+            batch_parts = mem::take(&mut builder.batch);
+            match &mut owners {
+                Some(index) => index.insert(name.as_ref(), &builder.parts),
+                None => batch_parts.push((name.as_ref().to_owned(), builder.parts.clone())),
+            }
+            let (next, part) = builder.write_manifest(cancel)?;
+            tx = next;
+            summary += part;
+            names.push(name.as_ref().to_owned());
+            (tx.notify_handler())(Notification::ComponentBatchProgress(done + 1, total));
         }
+
+        check_cancelled(cancel)?;
+        if self.sorted {
+            names.sort();
+        }
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let tx = self.set_list(&names, tx)?;
+        Ok((tx, summary))
     }
     pub fn find(&self, name: &str) -> Result<Option<Component>> {
-        let result = self.list()?;
-        Ok(result.into_iter().find(|c| (c.name() == name)))
+        let result = self.list_filtered(|n| n == name)?;
+        Ok(result.into_iter().next())
+    }
+    /// Returns the name of the installed component whose manifest lists
+    /// `path`, relative to the prefix, either directly or inside one of its
+    /// directories.
+    ///
+    /// This reads all the component manifests; use [`Components::owner_index`]
+    /// for repeated queries.
+    pub fn owner_of(&self, path: &Path) -> Result<Option<String>> {
+        Ok(self.owner_index()?.owner_of(path).map(ToOwned::to_owned))
+    }
+    /// Reads the component manifests into an index from paths to the
+    /// components owning them.
+    pub fn owner_index(&self) -> Result<OwnerIndex> {
+        let mut index = OwnerIndex::default();
+        for component in self.list()? {
+            index.insert(&component.name, &component.parts()?);
+        }
+        Ok(index)
+    }
+    /// Lists the files in the prefix that are not owned by any installed
+    /// component, relative to the prefix.
+    ///
+    /// Files directly inside the manifest directory are installation
+    /// metadata and are never reported.
+    pub fn find_orphans(&self) -> Result<Vec<PathBuf>> {
+        let mut owned_files = HashSet::new();
+        let mut owned_dirs = Vec::new();
+        for component in self.list()? {
+            for part in component.parts()? {
+                match &*part.0 {
+                    "dir" => owned_dirs.push(part.1),
+                    _ => {
+                        owned_files.insert(part.1);
+                    }
+                }
+            }
+        }
+
+        let manifest_dir = self.prefix.rel_manifest_dir();
+        let mut orphans = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop() {
+            for entry in utils::read_dir("install prefix", &self.prefix.abs_path(&dir))? {
+                let entry = entry?;
+                let path = dir.join(entry.file_name());
+                if owned_files.contains(&path) || owned_dirs.iter().any(|d| path.starts_with(d)) {
+                    continue;
+                }
+                if entry.file_type()?.is_dir() {
+                    pending.push(path);
+                } else if path.parent() != Some(manifest_dir.as_path()) {
+                    orphans.push(path);
+                }
+            }
+        }
+
+        orphans.sort();
+        Ok(orphans)
+    }
+    /// Compares the components installed here with those installed in
+    /// `other`. Each list in the result is sorted by name.
+    pub fn diff(&self, other: &Components) -> Result<ComponentDiff> {
+        let ours = self
+            .list()?
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<BTreeSet<_>>();
+        let theirs = other
+            .list()?
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<BTreeSet<_>>();
+        Ok(ComponentDiff {
+            only_in_self: ours.difference(&theirs).cloned().collect(),
+            only_in_other: theirs.difference(&ours).cloned().collect(),
+            in_both: ours.intersection(&theirs).cloned().collect(),
+        })
+    }
+    /// Overwrites the `components` file with `names`, dropping duplicates but
+    /// otherwise keeping their order, and updates the version file.
+    ///
+    /// This is meant for recovery: the component manifests are left alone,
+    /// so callers are responsible for keeping them consistent.
+    pub fn set_list<'a>(&self, names: &[&str], mut tx: Transaction<'a>) -> Result<Transaction<'a>> {
+        self.check_mutable()?;
+        self.lock(&mut tx)?;
+        let mut seen = HashSet::new();
+        let mut contents = String::new();
+        for name in names {
+            validate_name(name)?;
+            if seen.insert(*name) {
+                contents.push_str(name);
+                contents.push('\n');
+            }
+        }
+
+        let path = self.rel_components_file();
+        let abs_path = self.prefix.abs_path(&path);
+        tx.modify_file(path)?;
+        if !tx.is_dry_run() {
+            utils::write_file("components", &abs_path, &contents)?;
+        }
+        self.write_version(&mut tx)?;
+        Ok(tx)
+    }
+    /// Renames the installed component `old` to `new`, e.g. after it was
+    /// renamed upstream, leaving its files in place: only its entry in the
+    /// `components` file and its manifest are updated.
+    pub fn rename<'a>(
+        &self,
+        old: &str,
+        new: &str,
+        mut tx: Transaction<'a>,
+    ) -> Result<Transaction<'a>> {
+        self.check_mutable()?;
+        validate_name(new)?;
+        self.lock(&mut tx)?;
+        let names = self.list()?.into_iter().map(|c| c.name).collect::<Vec<_>>();
+        if !names.iter().any(|n| n == old) {
+            bail!("component '{old}' is not installed");
+        }
+        if names.iter().any(|n| n == new) {
+            bail!("component '{new}' is already installed");
+        }
+
+        let old_manifest = self.rel_component_manifest(old)?;
+        let abs_old_manifest = self.prefix.abs_path(&old_manifest);
+        tx.copy_file(new, self.rel_component_manifest(new)?, &abs_old_manifest)?;
+        tx.remove_file(old, old_manifest)?;
+
+        let names = names
+            .iter()
+            .map(|n| if n == old { new } else { n })
+            .collect::<Vec<_>>();
+        self.set_list(&names, tx)
+    }
+    /// Uninstalls every installed component in a single transaction.
+    ///
+    /// With [`UninstallPolicy::SkipCorrupt`], components whose manifest is
+    /// corrupt are left installed and their names are returned, so that the
+    /// healthy ones can still be removed. Otherwise the first corrupt
+    /// component fails the whole operation.
+    pub fn uninstall_all<'a>(
+        &self,
+        policy: UninstallPolicy,
+        mut tx: Transaction<'a>,
+        process: &Process,
+    ) -> Result<(Transaction<'a>, Vec<String>)> {
+        self.check_mutable()?;
+        self.lock(&mut tx)?;
+        let mut corrupt = Vec::new();
+        for component in self.list()? {
+            if policy == UninstallPolicy::SkipCorrupt && component.is_corrupt()? {
+                corrupt.push(component.name);
+                continue;
+            }
+            tx = component.uninstall(tx, process, None)?;
+        }
+        Ok((tx, corrupt))
+    }
+    /// Cross-checks the `components` file against the `manifest-<name>`
+    /// files in the manifest directory, and the format of each manifest
+    /// against the version file. Each list in the result is sorted by name.
+    ///
+    /// Manifests in another format usually mean that components from
+    /// different installer versions were mixed in this prefix, e.g. by an
+    /// interrupted upgrade; a warning is logged for each of them, as well as
+    /// for manifests in no known format.
+    pub fn audit(&self) -> Result<AuditReport> {
+        let listed = self
+            .list()?
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<BTreeSet<_>>();
+
+        let manifest_dir = self.prefix.manifest_dir();
+        let mut manifests = BTreeSet::new();
+        let mut mismatched_formats = Vec::new();
+        let mut corrupt_manifests = Vec::new();
+        if utils::is_directory(&manifest_dir) {
+            for entry in utils::read_dir("manifest", &manifest_dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name();
+                let Some(name) = file_name.to_str().and_then(|n| n.strip_prefix("manifest-"))
+                else {
+                    continue;
+                };
+                if entry.file_type()?.is_file() {
+                    let contents = utils::read_file("component", &entry.path())?;
+                    match manifest_format(&contents) {
+                        ManifestFormat::Current => {}
+                        ManifestFormat::Legacy => mismatched_formats.push(name.to_owned()),
+                        ManifestFormat::Unrecognized => corrupt_manifests.push(name.to_owned()),
+                    }
+                    manifests.insert(name.to_owned());
+                }
+            }
+        }
+
+        mismatched_formats.sort();
+        if !mismatched_formats.is_empty() {
+            let version = self.read_version()?;
+            for name in &mismatched_formats {
+                warn!(
+                    "manifest of component '{name}' does not match installer version {}",
+                    version.as_deref().unwrap_or(INSTALLER_VERSION)
+                );
+            }
+        }
+        corrupt_manifests.sort();
+        for name in &corrupt_manifests {
+            warn!("manifest of component '{name}' is corrupt");
+        }
+
+        Ok(AuditReport {
+            missing_manifests: listed.difference(&manifests).cloned().collect(),
+            unlisted_manifests: manifests.difference(&listed).cloned().collect(),
+            mismatched_formats,
+            corrupt_manifests,
+        })
+    }
+    /// Sums the sizes of the files owned by all installed components.
+    ///
+    /// Paths listed by several components, or inside a directory part that
+    /// has already been counted, are only counted once. Missing files count
+    /// as empty, with a warning.
+    pub fn total_size(&self) -> Result<u64> {
+        // Sorted by path, so the contents of a directory directly follow it
+        let mut parts = BTreeMap::new();
+        for component in self.list()? {
+            for ComponentPart(kind, path) in component.parts()? {
+                parts
+                    .entry(path)
+                    .or_insert_with(|| (kind, component.name.clone()));
+            }
+        }
+
+        let mut total = 0;
+        let mut counted_dir = None::<PathBuf>;
+        for (path, (kind, name)) in parts {
+            if counted_dir.as_ref().is_some_and(|d| path.starts_with(d)) {
+                continue;
+            }
+            let abs_path = self.prefix.abs_path(&path);
+            match &*kind {
+                "file" | "dir" => {}
+                "symlink" => continue,
+                _ => return Err(RustupError::CorruptComponent(name).into()),
+            }
+            if !utils::path_exists(&abs_path) {
+                warn!(
+                    "file '{}' of component '{name}' is missing",
+                    abs_path.display()
+                );
+                continue;
+            }
+            if kind == "dir" {
+                total += dir_size(&abs_path)?;
+                counted_dir = Some(path);
+            } else {
+                total += utils::file_size(&abs_path)?;
+            }
+        }
+        Ok(total)
+    }
+    /// Serializes the installed components as a JSON array of
+    /// [`ComponentInfo`], in the order of the `components` file.
+    pub fn to_json(&self) -> Result<String> {
+        let infos = self
+            .list()?
+            .into_iter()
+            .map(|c| {
+                Ok(ComponentInfo {
+                    manifest_path: c.manifest_file(),
+                    part_count: c.parts()?.len(),
+                    name: c.name,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        serde_json::to_string(&infos).context("failed to serialize the component list")
     }
     pub(crate) fn prefix(&self) -> InstallPrefix {
         self.prefix.clone()
     }
+    /// Checks that a file can be created in the manifest directory or, if it
+    /// doesn't exist yet, in its closest existing ancestor, so that a
+    /// read-only prefix is reported before anything is installed.
+    fn check_writable(&self) -> Result<()> {
+        let manifest_dir = self.prefix.manifest_dir();
+        let Some(dir) = manifest_dir.ancestors().find(|p| utils::is_directory(p)) else {
+            return Ok(());
+        };
+        match tempfile::Builder::new()
+            .prefix(".rustup-probe")
+            .tempfile_in(dir)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(RustupError::PrefixNotWritable {
+                path: dir.to_path_buf(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// The files directly inside a manifest directory, as taken by
+/// [`Components::snapshot`], so that tests can reset the installation
+/// metadata after mutating it.
+#[cfg(feature = "test")]
+#[derive(Clone, Debug)]
+pub struct ManifestSnapshot {
+    dir: PathBuf,
+    files: BTreeMap<std::ffi::OsString, Vec<u8>>,
+}
+
+#[cfg(feature = "test")]
+impl Components {
+    /// Reads the `components` file, the version file, the component
+    /// manifests and any other files directly inside the manifest directory
+    /// into memory.
+    pub fn snapshot(&self) -> Result<ManifestSnapshot> {
+        let dir = self.prefix.manifest_dir();
+        let mut files = BTreeMap::new();
+        if utils::is_directory(&dir) {
+            for entry in utils::read_dir("manifest", &dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    let contents =
+                        fs::read(entry.path()).with_context(|| RustupError::ReadingFile {
+                            name: "manifest",
+                            path: entry.path(),
+                        })?;
+                    files.insert(entry.file_name(), contents);
+                }
+            }
+        }
+        Ok(ManifestSnapshot { dir, files })
+    }
+}
+
+#[cfg(feature = "test")]
+impl ManifestSnapshot {
+    /// Puts the manifest directory back the way it was when the snapshot
+    /// was taken, removing the files that have been added since. The
+    /// components' own files are left alone.
+    pub fn restore(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| RustupError::CreatingDirectory {
+            name: "manifest",
+            path: self.dir.clone(),
+        })?;
+        for entry in utils::read_dir("manifest", &self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && !self.files.contains_key(&entry.file_name()) {
+                utils::remove_file("manifest", &entry.path())?;
+            }
+        }
+        for (name, contents) in &self.files {
+            let path = self.dir.join(name);
+            fs::write(&path, contents).with_context(|| RustupError::WritingFile {
+                name: "manifest",
+                path,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// How [`Components::uninstall_all`] deals with corrupt components.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UninstallPolicy {
+    /// Stop at the first corrupt component, rolling back everything.
+    #[default]
+    FailFast,
+    /// Uninstall the healthy components and report the corrupt ones.
+    SkipCorrupt,
+}
+
+/// The result of [`Components::audit`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Components listed in the `components` file without a manifest.
+    pub missing_manifests: Vec<String>,
+    /// Components with a manifest that the `components` file doesn't list.
+    pub unlisted_manifests: Vec<String>,
+    /// Components whose manifest is not in the format of the installer
+    /// version recorded in the version file.
+    pub mismatched_formats: Vec<String>,
+    /// Components whose manifest is in no format known to any installer
+    /// version.
+    pub corrupt_manifests: Vec<String>,
+}
+
+impl AuditReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_manifests.is_empty()
+            && self.unlisted_manifests.is_empty()
+            && self.mismatched_formats.is_empty()
+            && self.corrupt_manifests.is_empty()
+    }
+}
+
+/// An installed component, as serialized by [`Components::to_json`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentInfo {
+    pub name: String,
+    /// The absolute path of the component's `manifest-<name>` file.
+    pub manifest_path: PathBuf,
+    /// The number of files and directories listed in the manifest.
+    pub part_count: usize,
+}
+
+/// The result of [`Components::owner_index`].
+#[derive(Debug, Default)]
+pub struct OwnerIndex {
+    /// Every part, by path.
+    paths: HashMap<PathBuf, String>,
+    /// The `dir` parts, by path.
+    dirs: HashMap<PathBuf, String>,
+}
+
+impl OwnerIndex {
+    /// Records `name` as the owner of `parts`, unless another component
+    /// already claimed them.
+    fn insert(&mut self, name: &str, parts: &[ComponentPart]) {
+        for ComponentPart(kind, path) in parts {
+            if kind == "dir" {
+                self.dirs
+                    .entry(path.clone())
+                    .or_insert_with(|| name.to_owned());
+            }
+            self.paths
+                .entry(path.clone())
+                .or_insert_with(|| name.to_owned());
+        }
+    }
+    /// Like [`Components::owner_of`]. A component listing `path` itself
+    /// takes precedence over one listing a directory containing it.
+    pub fn owner_of(&self, path: &Path) -> Option<&str> {
+        self.paths
+            .get(path)
+            .or_else(|| path.ancestors().skip(1).find_map(|p| self.dirs.get(p)))
+            .map(String::as_str)
+    }
+}
+
+/// The result of [`Components::diff`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ComponentDiff {
+    pub only_in_self: Vec<String>,
+    pub only_in_other: Vec<String>,
+    pub in_both: Vec<String>,
+}
+
+/// A part of a component that doesn't match what is on disk, as reported by
+/// [`Component::verify_against_disk`]. Paths are relative to the prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileDiscrepancy {
+    /// Nothing exists at the path.
+    Missing(PathBuf),
+    /// Something other than the listed kind of part exists at the path.
+    WrongKind { path: PathBuf, expected: String },
+    /// The file part is empty.
+    Empty(PathBuf),
+}
+
+/// What [`ComponentBuilder::finish`] installed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InstallSummary {
+    /// The number of file parts.
+    pub files: usize,
+    /// The number of directory parts.
+    pub dirs: usize,
+    /// The total size of all parts, including the contents of directories.
+    ///
+    /// Measuring this takes an extra `stat` per file and a walk of every
+    /// directory, so it is only done while a subscriber is interested in
+    /// [`INSTALL_SUMMARY_TARGET`] events, and is `0` otherwise.
+    pub bytes: u64,
+}
+
+impl std::ops::AddAssign for InstallSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.bytes += other.bytes;
+    }
 }
 
 pub(crate) struct ComponentBuilder<'a> {
     components: Components,
     name: String,
     parts: Vec<ComponentPart>,
+    summary: InstallSummary,
+    /// Whether to measure [`InstallSummary::bytes`].
+    count_bytes: bool,
+    /// Built on the first destination that already exists, then reused.
+    owners: Option<OwnerIndex>,
+    /// The parts of the components added before this one by
+    /// [`Components::install_many`], which the `components` file doesn't
+    /// list yet.
+    batch: Vec<(String, Vec<ComponentPart>)>,
     tx: Transaction<'a>,
 }
 
 impl<'a> ComponentBuilder<'a> {
     pub(crate) fn copy_file(&mut self, path: PathBuf, src: &Path) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.add_file_to_summary(src)?;
         self.parts
             .push(ComponentPart("file".to_owned(), path.clone()));
         self.tx.copy_file(&self.name, path, src)
     }
+    /// Like [`ComponentBuilder::copy_file`], but hardlinks `src` instead of
+    /// copying it when possible, for files known to be identical to one in
+    /// a store shared between toolchains.
+    ///
+    /// The link is recorded as a normal `file` part, so uninstalling the
+    /// component only removes the link and leaves `src` alone.
+    pub(crate) fn link_file(&mut self, path: PathBuf, src: &Path) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.add_file_to_summary(src)?;
+        self.parts
+            .push(ComponentPart("file".to_owned(), path.clone()));
+        self.tx.link_file(&self.name, path, src)
+    }
+    /// Records a `file` part and writes `contents` to it, for packages that
+    /// aren't unpacked to disk first.
+    pub(crate) fn write_file(&mut self, path: PathBuf, contents: &mut dyn Read) -> Result<()> {
+        self.check_not_owned(&path)?;
+        let abs_path = self.components.prefix.abs_path(&path);
+        let mut file = self.tx.add_file(&self.name, path.clone())?;
+        let bytes = io::copy(contents, &mut file)
+            .with_context(|| format!("could not write file '{}'", abs_path.display()))?;
+        self.summary.files += 1;
+        self.summary.bytes += bytes;
+        self.parts.push(ComponentPart("file".to_owned(), path));
+        Ok(())
+    }
+    /// Records a `symlink` part and creates a symlink to `target` at `path`.
+    pub(crate) fn create_symlink(&mut self, path: PathBuf, target: PathBuf) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.summary.files += 1;
+        self.parts
+            .push(ComponentPart("symlink".to_owned(), path.clone()));
+        self.tx.create_symlink(&self.name, path, &target)
+    }
     pub(crate) fn copy_dir(&mut self, path: PathBuf, src: &Path) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.add_dir_to_summary(src)?;
         self.parts
             .push(ComponentPart("dir".to_owned(), path.clone()));
         self.tx.copy_dir(&self.name, path, src)
     }
     pub(crate) fn move_file(&mut self, path: PathBuf, src: &Path) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.add_file_to_summary(src)?;
         self.parts
             .push(ComponentPart("file".to_owned(), path.clone()));
         self.tx.move_file(&self.name, path, src)
     }
     pub(crate) fn move_dir(&mut self, path: PathBuf, src: &Path) -> Result<()> {
+        self.check_not_owned(&path)?;
+        self.add_dir_to_summary(src)?;
         self.parts
             .push(ComponentPart("dir".to_owned(), path.clone()));
         self.tx.move_dir(&self.name, path, src)
     }
-    pub(crate) fn finish(mut self) -> Result<Transaction<'a>> {
-        // Write component manifest
-        let path = self.components.rel_component_manifest(&self.name);
+    /// Reports that `done` of the `total` parts have been installed, the
+    /// last one being `path`.
+    pub(crate) fn report_progress(&mut self, path: &Path, done: usize, total: usize) {
+        self.tx.report_progress("install", path, done, total);
+    }
+    fn add_file_to_summary(&mut self, src: &Path) -> Result<()> {
+        self.summary.files += 1;
+        if self.count_bytes {
+            self.summary.bytes += utils::file_size(src)?;
+        }
+        Ok(())
+    }
+    fn add_dir_to_summary(&mut self, src: &Path) -> Result<()> {
+        self.summary.dirs += 1;
+        if self.count_bytes {
+            self.summary.bytes += dir_size(src)?;
+        }
+        Ok(())
+    }
+    /// When the destination already exists, names the installed component
+    /// that owns it: two components claiming the same path is a packaging
+    /// bug, and installing the second would break uninstalling either.
+    /// Otherwise the transaction reports the conflict itself.
+    fn check_not_owned(&mut self, path: &Path) -> Result<()> {
+        if !utils::path_exists(self.components.prefix.abs_path(path)) {
+            return Ok(());
+        }
+        let owners = match &mut self.owners {
+            Some(owners) => owners,
+            owners => {
+                let mut index = self.components.owner_index()?;
+                for (name, parts) in &self.batch {
+                    index.insert(name, parts);
+                }
+                owners.insert(index)
+            }
+        };
+        if let Some(owner) = owners.owner_of(path) {
+            return Err(RustupError::ComponentPathOwned {
+                name: self.name.clone(),
+                owner: owner.to_owned(),
+                path: path.to_path_buf(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+    /// Records the component in the installation metadata, returning the
+    /// transaction along with a summary of the installed parts.
+    ///
+    /// If `cancel` is set, it is checked between file operations and, once
+    /// raised, the transaction is dropped and thereby rolled back.
+    pub(crate) fn finish(
+        self,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(Transaction<'a>, InstallSummary)> {
+        let components = self.components.clone();
+        let name = self.name.clone();
+        let (mut tx, summary) = self.write_manifest(cancel)?;
+
+        // Add component to components file
+        check_cancelled(cancel)?;
+        let path = components.rel_components_file();
+        let abs_path = components.prefix.abs_path(&path);
+        tx.modify_file(path)?;
+        if !tx.is_dry_run() && components.sorted {
+            let mut names = components
+                .list()?
+                .into_iter()
+                .map(|c| c.name)
+                .collect::<Vec<_>>();
+            names.push(name);
+            names.sort();
+            let contents = names.iter().map(|n| format!("{n}\n")).collect::<String>();
+            utils::write_file("components", &abs_path, &contents)?;
+        } else if !tx.is_dry_run() {
+            utils::append_file("components", &abs_path, &name)?;
+        }
+
+        // Drop in the version file for future use
+        check_cancelled(cancel)?;
+        components.write_version(&mut tx)?;
+
+        Ok((tx, summary))
+    }
+    /// Writes the component manifest, leaving the `components` file and the
+    /// version file to the caller.
+    fn write_manifest(
+        mut self,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(Transaction<'a>, InstallSummary)> {
+        check_cancelled(cancel)?;
+
+        let path = self.components.rel_component_manifest(&self.name)?;
         let abs_path = self.components.prefix.abs_path(&path);
         let mut file = BufWriter::new(self.tx.add_file(&self.name, path)?);
         for part in self.parts {
@@ -131,29 +1136,118 @@ impl<'a> ComponentBuilder<'a> {
             utils::write_line("component", &mut file, &abs_path, &part.encode())?;
         }
 
-        // Add component to components file
-        let path = self.components.rel_components_file();
-        let abs_path = self.components.prefix.abs_path(&path);
-        self.tx.modify_file(path)?;
-        utils::append_file("components", &abs_path, &self.name)?;
+        Ok((self.tx, self.summary))
+    }
+}
 
-        // Drop in the version file for future use
-        self.components.write_version(&mut self.tx)?;
+/// The total size of the files below `path`, not following symlinks.
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in utils::read_dir("component", path)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|| format!("could not read metadata of '{}'", path.display()))?;
+        size += if metadata.is_dir() {
+            dir_size(&path)?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// The format of a component manifest, as inferred by [`manifest_format`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ManifestFormat {
+    /// `kind:path` lines with a known kind and a relative path, as written
+    /// by the current installer version.
+    Current,
+    /// Bare paths without a kind, as written by older installers.
+    Legacy,
+    /// A mix of the above, or `kind:path` lines with an unknown kind or an
+    /// absolute path.
+    Unrecognized,
+}
+
+/// Infers the installer version that wrote a component manifest.
+///
+/// Manifests don't record their version, so this goes by the shape of their
+/// lines, which must all agree.
+fn manifest_format(contents: &str) -> ManifestFormat {
+    let known_kind = |part: &ComponentPart| matches!(&*part.0, "file" | "dir" | "symlink");
+    let (mut current, mut legacy) = (true, true);
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        // Only lines without a kind separator can be bare paths
+        if !line.contains(':') {
+            current = false;
+            continue;
+        }
+        legacy = false;
+        match ComponentPart::decode(line).filter(known_kind) {
+            Some(ComponentPart(_, path)) => current &= path.is_relative(),
+            None => current = false,
+        }
+    }
+    match (current, legacy) {
+        (true, _) => ManifestFormat::Current,
+        (false, true) => ManifestFormat::Legacy,
+        (false, false) => ManifestFormat::Unrecognized,
+    }
+}
+
+/// Rejects component names that [`Components::add`] can't record.
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['\n', '\r', '/', '\\']) {
+        return Err(RustupError::InvalidComponentName(name.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// The position of `name` in [`INSTALL_PRIORITY`], either bare or followed
+/// by a target triple as in `rust-std-x86_64-unknown-linux-gnu`, or
+/// `usize::MAX` if it isn't listed.
+fn install_priority(name: &str) -> usize {
+    INSTALL_PRIORITY
+        .iter()
+        .position(|pkg| match name.strip_prefix(pkg) {
+            Some("") => true,
+            Some(rest) => rest.strip_prefix('-').is_some_and(is_known_triple),
+            None => false,
+        })
+        .unwrap_or(usize::MAX)
+}
 
-        Ok(self.tx)
+fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<()> {
+    match cancel {
+        Some(cancel) if cancel.load(Ordering::SeqCst) => Err(RustupError::Cancelled.into()),
+        _ => Ok(()),
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ComponentPart(pub String, pub PathBuf);
 
 impl ComponentPart {
+    /// Formats the part as a `kind:path` manifest line.
+    ///
+    /// The path is written verbatim, as rust-installer does, so that it may
+    /// contain colons: [`ComponentPart::decode`] splits on the first colon
+    /// only. This is why the kind must not contain any.
     pub(crate) fn encode(&self) -> String {
+        debug_assert!(!self.0.contains(':'), "invalid part kind: {:?}", self.0);
         format!("{}:{}", &self.0, &self.1.to_string_lossy())
     }
+    /// Parses a `kind:path` manifest line, returning `None` if the line has
+    /// no separator or either half of it is empty.
+    ///
+    /// A trailing `\r` left over from a CRLF line ending is ignored.
     pub(crate) fn decode(line: &str) -> Option<Self> {
-        line.find(':')
-            .map(|pos| Self(line[0..pos].to_owned(), PathBuf::from(&line[(pos + 1)..])))
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (kind, path) = line.split_once(':')?;
+        if kind.is_empty() || path.is_empty() {
+            return None;
+        }
+        Some(Self(kind.to_owned(), PathBuf::from(path)))
     }
 }
 
@@ -161,6 +1255,9 @@ impl ComponentPart {
 pub struct Component {
     components: Components,
     name: String,
+    /// The parsed manifest, along with the modification time and length of
+    /// the file it was parsed from.
+    parts: Arc<Mutex<Option<(SystemTime, u64, Vec<ComponentPart>)>>>,
 }
 
 impl Component {
@@ -178,35 +1275,317 @@ impl Component {
     pub(crate) fn name(&self) -> &str {
         &self.name
     }
+    /// The parts listed in the component manifest.
+    ///
+    /// The manifest is only re-read if it changed since the last call.
     pub(crate) fn parts(&self) -> Result<Vec<ComponentPart>> {
+        let path = self.manifest_file();
+        let metadata = fs::metadata(&path).with_context(|| RustupError::ReadingFile {
+            name: "component",
+            path: path.clone(),
+        })?;
+        let modified = metadata.modified()?;
+        let len = metadata.len();
+
+        let mut cache = self.parts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((cached_modified, cached_len, parts)) = &*cache {
+            if *cached_modified == modified && *cached_len == len {
+                return Ok(parts.clone());
+            }
+        }
+
         let mut result = Vec::new();
-        for line in utils::read_file("component", &self.manifest_file())?.lines() {
+        for line in utils::read_file("component", &path)?.lines() {
             result.push(
                 ComponentPart::decode(line)
                     .ok_or_else(|| RustupError::CorruptComponent(self.name.clone()))?,
             );
         }
+        *cache = Some((modified, len, result.clone()));
         Ok(result)
     }
+    /// Whether the manifest can't be parsed or lists a part of an unknown
+    /// kind, i.e. whether [`Component::uninstall`] would fail with
+    /// [`RustupError::CorruptComponent`] before removing anything.
+    fn is_corrupt(&self) -> Result<bool> {
+        let parts = match self.parts() {
+            Ok(parts) => parts,
+            Err(e) => {
+                return match e.downcast_ref::<RustupError>() {
+                    Some(RustupError::CorruptComponent(_)) => Ok(true),
+                    _ => Err(e),
+                };
+            }
+        };
+        Ok(parts
+            .iter()
+            .any(|p| !matches!(&*p.0, "file" | "dir" | "symlink")))
+    }
+    /// Opens a `file` part of this component for reading, e.g. to hash it
+    /// without loading it all into memory.
+    pub fn open_part(&self, part: &ComponentPart) -> io::Result<impl Read> {
+        if part.0 != "file" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a file part", part.1.display()),
+            ));
+        }
+        let path = self.components.prefix.abs_path(&part.1);
+        Ok(BufReader::new(fs::File::open(path)?))
+    }
+    /// Groups the parts by the first component of their path, e.g. `bin` or
+    /// `lib`, keeping the manifest order within each group.
+    pub fn parts_by_root(&self) -> Result<BTreeMap<String, Vec<ComponentPart>>> {
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for part in self.parts()? {
+            let root = part
+                .1
+                .components()
+                .next()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .unwrap_or_default();
+            groups.entry(root).or_default().push(part);
+        }
+        Ok(groups)
+    }
+    /// Estimates what uninstalling this component would free, as the number
+    /// of file parts and their total size in bytes.
+    ///
+    /// As with [`Components::total_size`], directory parts contribute the
+    /// size of everything below them, and parts inside them aren't counted
+    /// twice.
+    pub fn uninstall_estimate(&self) -> Result<(usize, u64)> {
+        // Sorted by path, so the contents of a directory directly follow it
+        let mut parts = self.parts()?;
+        parts.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let mut files = 0;
+        let mut bytes = 0;
+        let mut counted_dir = None::<PathBuf>;
+        for ComponentPart(kind, path) in parts {
+            match &*kind {
+                "file" => files += 1,
+                "dir" => {}
+                "symlink" => {
+                    files += 1;
+                    continue;
+                }
+                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
+            }
+            if counted_dir.as_ref().is_some_and(|d| path.starts_with(d)) {
+                continue;
+            }
+            let abs_path = self.components.prefix.abs_path(&path);
+            if kind == "dir" {
+                bytes += dir_size(&abs_path)?;
+                counted_dir = Some(path);
+            } else {
+                bytes += utils::file_size(&abs_path)?;
+            }
+        }
+        Ok((files, bytes))
+    }
+    /// The directories, relative to the prefix, that only this component
+    /// has parts in: those uninstalling it would leave empty. This includes
+    /// its directory parts and the directories containing its other parts,
+    /// but never the manifest directory or its ancestors.
+    pub fn owned_dirs(&self) -> Result<Vec<PathBuf>> {
+        let manifest_dir = self.components.prefix.rel_manifest_dir();
+        let mut candidates = BTreeSet::new();
+        for ComponentPart(kind, path) in self.parts()? {
+            let mut dirs = path.ancestors().skip(1).collect::<Vec<_>>();
+            if kind == "dir" {
+                dirs.push(path.as_path());
+            }
+            for dir in dirs {
+                if !dir.as_os_str().is_empty() && !manifest_dir.starts_with(dir) {
+                    candidates.insert(dir.to_path_buf());
+                }
+            }
+        }
+
+        for other in self.components.list()? {
+            if other.name == self.name {
+                continue;
+            }
+            for ComponentPart(kind, path) in other.parts()? {
+                candidates.retain(|dir| {
+                    !path.starts_with(dir) && !(kind == "dir" && dir.starts_with(&path))
+                });
+            }
+        }
+        Ok(candidates.into_iter().collect())
+    }
+    /// Compares the parts listed in the manifest with what is on disk,
+    /// without modifying anything.
+    pub fn verify_against_disk(&self) -> Result<Vec<FileDiscrepancy>> {
+        let mut discrepancies = Vec::new();
+        for ComponentPart(kind, path) in self.parts()? {
+            let abs_path = self.components.prefix.abs_path(&path);
+            let metadata = match fs::symlink_metadata(&abs_path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    discrepancies.push(FileDiscrepancy::Missing(path));
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("could not read metadata of '{}'", abs_path.display())
+                    })
+                }
+            };
+            let file_type = metadata.file_type();
+            let matches = match &*kind {
+                "file" => file_type.is_file(),
+                "dir" => file_type.is_dir(),
+                "symlink" => file_type.is_symlink(),
+                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
+            };
+            if !matches {
+                discrepancies.push(FileDiscrepancy::WrongKind {
+                    path,
+                    expected: kind,
+                });
+            } else if kind == "file" && metadata.len() == 0 {
+                discrepancies.push(FileDiscrepancy::Empty(path));
+            }
+        }
+        Ok(discrepancies)
+    }
+    /// Like [`Component::uninstall`], but moves the files out of the way on
+    /// a blocking thread with [`tokio::task::spawn_blocking`], so that other
+    /// tasks on the tokio runtime, of any flavor, keep making progress.
+    ///
+    /// The transaction itself can't be sent to that thread, so the removals
+    /// are planned beforehand and recorded in it afterwards. Everything that
+    /// was removed is still rolled back if the transaction is dropped.
+    /// Notifications about retried renames are not reported.
+    pub async fn uninstall_async<'a>(
+        &self,
+        mut tx: Transaction<'a>,
+        process: &Process,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<Transaction<'a>> {
+        if tx.is_dry_run() {
+            return self.uninstall(tx, process, cancel.as_deref());
+        }
+        let parts = self.begin_uninstall(&mut tx, process)?;
+        let mut planned = Vec::with_capacity(parts.len());
+        for ComponentPart(kind, path) in parts.into_iter().rev() {
+            let is_dir = match &*kind {
+                "file" | "symlink" => false,
+                "dir" => true,
+                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
+            };
+            planned.push(tx.plan_removal(&self.name, path, is_dir)?);
+        }
+
+        let moves = planned
+            .iter()
+            .map(|removal| (removal.from.clone(), removal.to.clone()))
+            .collect::<Vec<_>>();
+        let retry = tx.fs_retry();
+        let task_process = process.clone();
+        let task_cancel = cancel.clone();
+        let (moved, result) = tokio::task::spawn_blocking(move || {
+            let notify = |_: Notification<'_>| ();
+            for (moved, (from, to)) in moves.iter().enumerate() {
+                let result = check_cancelled(task_cancel.as_deref()).and_then(|()| {
+                    retry.run(|| utils::rename("component", from, to, &notify, &task_process))
+                });
+                if let Err(e) = result {
+                    return (moved, Err(e));
+                }
+            }
+            (moves.len(), Ok(()))
+        })
+        .await?;
+
+        // Record what was moved before reporting any error, so that it is
+        // rolled back.
+        let total = planned.len();
+        let mut removed = Vec::with_capacity(moved);
+        for (done, removal) in planned.into_iter().take(moved).enumerate() {
+            tx.report_progress("uninstall", removal.relpath(), done + 1, total);
+            removed.push(removal.relpath().to_owned());
+            tx.record_removal(removal);
+        }
+        result?;
+        self.finish_uninstall(tx, removed, cancel.as_deref())
+    }
+    /// Removes the component's files and its entry in the installation
+    /// metadata.
+    ///
+    /// If `cancel` is set, it is checked between file operations and, once
+    /// raised, the transaction is dropped and thereby rolled back.
     pub fn uninstall<'a>(
         &self,
         mut tx: Transaction<'a>,
         process: &Process,
+        cancel: Option<&AtomicBool>,
     ) -> Result<Transaction<'a>> {
+        let parts = self.begin_uninstall(&mut tx, process)?;
+        let total = parts.len();
+        let mut removed = Vec::with_capacity(total);
+        for (done, part) in parts.into_iter().rev().enumerate() {
+            check_cancelled(cancel)?;
+            match &*part.0 {
+                "file" | "symlink" => tx.remove_file(&self.name, part.1.clone())?,
+                "dir" => tx.remove_dir(&self.name, part.1.clone())?,
+                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
+            }
+            tx.report_progress("uninstall", &part.1, done + 1, total);
+            removed.push(part.1);
+        }
+        self.finish_uninstall(tx, removed, cancel)
+    }
+    /// Drops the component from the `components` file and returns its
+    /// parts, which are then up to the caller to remove.
+    fn begin_uninstall(
+        &self,
+        tx: &mut Transaction<'_>,
+        process: &Process,
+    ) -> Result<Vec<ComponentPart>> {
+        self.components.check_mutable()?;
+        self.components.lock(tx)?;
+
         // Update components file
         let path = self.components.rel_components_file();
         let abs_path = self.components.prefix.abs_path(&path);
-        let temp = tx.temp().new_file()?;
-        utils::filter_file("components", &abs_path, &temp, |l| l != self.name)?;
         tx.modify_file(path)?;
-        utils::rename("components", &temp, &abs_path, tx.notify_handler(), process)?;
+        if !tx.is_dry_run() {
+            // Stage the new list next to the old one: the rename is then
+            // atomic, and `Components::open` cleans up if we die before it.
+            let staged = self
+                .components
+                .prefix
+                .abs_path(self.components.rel_staged_components_file());
+            utils::filter_file("components", &abs_path, &staged, |l| l != self.name)?;
+            utils::rename(
+                "components",
+                &staged,
+                &abs_path,
+                tx.notify_handler(),
+                process,
+            )?;
+        }
 
         // TODO: If this is the last component remove the components file
         // and the version file.
 
+        self.parts()
+    }
+    /// Prunes the directories that the `removed` parts left empty and
+    /// removes the component manifest.
+    fn finish_uninstall<'a>(
+        &self,
+        mut tx: Transaction<'a>,
+        removed: Vec<PathBuf>,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Transaction<'a>> {
         // Track visited directories
         use std::collections::hash_set::IntoIter;
-        use std::collections::HashSet;
+        use std::collections::{BTreeSet, HashSet};
         use std::fs::read_dir;
 
         // dirs will contain the set of longest disjoint directory paths seen
@@ -298,27 +1677,84 @@ impl Component {
             }
         }
 
-        // Remove parts
+        // Prune the directories the parts were removed from
         let mut pset = PruneSet {
             dirs: HashSet::new(),
             ancestors: HashSet::new(),
             prefix: self.components.prefix.abs_path(""),
         };
-        for part in self.parts()?.into_iter().rev() {
-            match &*part.0 {
-                "file" => tx.remove_file(&self.name, part.1.clone())?,
-                "dir" => tx.remove_dir(&self.name, part.1.clone())?,
-                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
-            }
-            pset.seen(part.1);
+        for path in removed {
+            pset.seen(path);
         }
         for empty_dir in pset {
+            check_cancelled(cancel)?;
             tx.remove_dir(&self.name, empty_dir)?;
         }
 
         // Remove component manifest
+        check_cancelled(cancel)?;
         tx.remove_file(&self.name, self.rel_manifest_file())?;
 
+        if !tx.is_dry_run() {
+            self.check_uninstalled(tx.notify_handler())?;
+        }
+
+        Ok(tx)
+    }
+
+    /// Removes some of the component's parts, e.g. a single corrupted binary
+    /// that is to be reinstalled, and drops them from its manifest.
+    ///
+    /// Unlike [`Component::uninstall`], the component stays listed in the
+    /// `components` file.
+    pub fn remove_parts<'a>(
+        &self,
+        parts: &[ComponentPart],
+        mut tx: Transaction<'a>,
+    ) -> Result<Transaction<'a>> {
+        self.components.check_mutable()?;
+        self.components.lock(&mut tx)?;
+
+        let mut remaining = self.parts()?;
+        for part in parts {
+            let Some(idx) = remaining.iter().position(|p| p == part) else {
+                return Err(RustupError::ComponentPartNotFound {
+                    name: self.name.clone(),
+                    path: part.1.clone(),
+                }
+                .into());
+            };
+            remaining.remove(idx);
+            match &*part.0 {
+                "file" | "symlink" => tx.remove_file(&self.name, part.1.clone())?,
+                "dir" => tx.remove_dir(&self.name, part.1.clone())?,
+                _ => return Err(RustupError::CorruptComponent(self.name.clone()).into()),
+            }
+        }
+
+        // Rewrite the manifest with the parts that are left
+        let path = self.rel_manifest_file();
+        tx.modify_file(path)?;
+        if !tx.is_dry_run() {
+            let contents = remaining
+                .iter()
+                .map(|part| part.encode() + "\n")
+                .collect::<String>();
+            utils::write_file("component", &self.manifest_file(), &contents)?;
+        }
+
         Ok(tx)
     }
+
+    /// Warns if the component is still listed in the `components` file, e.g.
+    /// because another process re-added it while it was being uninstalled.
+    pub(crate) fn check_uninstalled(
+        &self,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<()> {
+        if self.components.find(&self.name)?.is_some() {
+            notify_handler(Notification::ComponentStillListed(&self.name));
+        }
+        Ok(())
+    }
 }
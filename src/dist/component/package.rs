@@ -33,7 +33,7 @@ pub trait Package: fmt::Debug {
         component: &str,
         short_name: Option<&str>,
         tx: Transaction<'a>,
-    ) -> Result<Transaction<'a>>;
+    ) -> Result<(Transaction<'a>, InstallSummary)>;
     fn components(&self) -> Vec<String>;
 }
 
@@ -42,6 +42,7 @@ pub struct DirectoryPackage {
     path: PathBuf,
     components: HashSet<String>,
     copy: bool,
+    link: bool,
 }
 
 impl DirectoryPackage {
@@ -57,36 +58,26 @@ impl DirectoryPackage {
             path,
             components,
             copy,
+            link: false,
         })
     }
-}
 
-fn validate_installer_version(path: &Path) -> Result<()> {
-    let file = utils::read_file("installer version", &path.join(VERSION_FILE))?;
-    let v = file.trim();
-    if v == INSTALLER_VERSION {
-        Ok(())
-    } else {
-        Err(anyhow!(format!("unsupported installer version: {v}")))
+    /// When copying, hardlinks the package's files into the prefix instead,
+    /// falling back to a copy where that fails. Meant for packages kept in a
+    /// store shared between toolchains, whose files must not change later.
+    pub fn with_hardlinks(mut self, link: bool) -> Self {
+        self.link = link;
+        self
     }
-}
 
-impl Package for DirectoryPackage {
-    fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
-        self.components.contains(component)
-            || if let Some(n) = short_name {
-                self.components.contains(n)
-            } else {
-                false
-            }
-    }
-    fn install<'a>(
+    /// Adds the parts of component `name`, or `short_name` if the package
+    /// lists that instead, to `builder`.
+    pub(crate) fn add_parts(
         &self,
-        target: &Components,
+        builder: &mut ComponentBuilder<'_>,
         name: &str,
         short_name: Option<&str>,
-        tx: Transaction<'a>,
-    ) -> Result<Transaction<'a>> {
+    ) -> Result<()> {
         let actual_name = if self.components.contains(name) {
             name
         } else if let Some(n) = short_name {
@@ -98,9 +89,9 @@ impl Package for DirectoryPackage {
         let root = self.path.join(actual_name);
 
         let manifest = utils::read_file("package manifest", &root.join("manifest.in"))?;
-        let mut builder = target.add(name, tx);
 
-        for l in manifest.lines() {
+        let total = manifest.lines().count();
+        for (done, l) in manifest.lines().enumerate() {
             let part = ComponentPart::decode(l)
                 .ok_or_else(|| RustupError::CorruptComponent(name.to_owned()))?;
 
@@ -109,10 +100,12 @@ impl Package for DirectoryPackage {
 
             match &*part.0 {
                 "file" => {
-                    if self.copy {
-                        builder.copy_file(path.clone(), &src_path)?
-                    } else {
+                    if !self.copy {
                         builder.move_file(path.clone(), &src_path)?
+                    } else if self.link {
+                        builder.link_file(path.clone(), &src_path)?
+                    } else {
+                        builder.copy_file(path.clone(), &src_path)?
                     }
                 }
                 "dir" => {
@@ -122,13 +115,50 @@ impl Package for DirectoryPackage {
                         builder.move_dir(path.clone(), &src_path)?
                     }
                 }
+                "symlink" => {
+                    let target = std::fs::read_link(&src_path).with_context(|| {
+                        format!("could not read symlink '{}'", src_path.display())
+                    })?;
+                    builder.create_symlink(path.clone(), target)?
+                }
                 _ => return Err(RustupError::CorruptComponent(name.to_owned()).into()),
             }
+            builder.report_progress(&path, done + 1, total);
         }
 
-        let tx = builder.finish()?;
+        Ok(())
+    }
+}
+
+fn validate_installer_version(path: &Path) -> Result<()> {
+    let file = utils::read_file("installer version", &path.join(VERSION_FILE))?;
+    let v = file.trim();
+    if v == INSTALLER_VERSION {
+        Ok(())
+    } else {
+        Err(anyhow!(format!("unsupported installer version: {v}")))
+    }
+}
 
-        Ok(tx)
+impl Package for DirectoryPackage {
+    fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
+        self.components.contains(component)
+            || if let Some(n) = short_name {
+                self.components.contains(n)
+            } else {
+                false
+            }
+    }
+    fn install<'a>(
+        &self,
+        target: &Components,
+        name: &str,
+        short_name: Option<&str>,
+        tx: Transaction<'a>,
+    ) -> Result<(Transaction<'a>, InstallSummary)> {
+        let mut builder = target.add(name, tx)?;
+        self.add_parts(&mut builder, name, short_name)?;
+        builder.finish(None)
     }
 
     fn components(&self) -> Vec<String> {
@@ -136,6 +166,55 @@ impl Package for DirectoryPackage {
     }
 }
 
+/// The boundary for packages in archive formats rustup doesn't know about:
+/// an implementation decompresses the archive its own way and hands over
+/// the files of each component, which [`ExtractPackage`] then installs.
+pub trait Extract: fmt::Debug {
+    /// The names of the components in the package.
+    fn components(&self) -> Vec<String>;
+    /// Calls `add` with the path, relative to the install prefix, and the
+    /// contents of each file of `component`.
+    fn extract(
+        &self,
+        component: &str,
+        add: &mut dyn FnMut(PathBuf, &mut dyn Read) -> Result<()>,
+    ) -> Result<()>;
+}
+
+/// Installs components from an [`Extract`] implementation.
+#[derive(Debug)]
+pub struct ExtractPackage<E>(pub E);
+
+impl<E: Extract> Package for ExtractPackage<E> {
+    fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
+        let components = self.0.components();
+        components
+            .iter()
+            .any(|c| c == component || Some(&**c) == short_name)
+    }
+    fn install<'a>(
+        &self,
+        target: &Components,
+        name: &str,
+        short_name: Option<&str>,
+        tx: Transaction<'a>,
+    ) -> Result<(Transaction<'a>, InstallSummary)> {
+        let actual_name = match short_name {
+            Some(n) if !self.0.components().iter().any(|c| c == name) => n,
+            _ => name,
+        };
+
+        let mut builder = target.add(name, tx)?;
+        self.0.extract(actual_name, &mut |path, contents| {
+            builder.write_file(path, contents)
+        })?;
+        builder.finish(None)
+    }
+    fn components(&self) -> Vec<String> {
+        self.0.components()
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)] // temp::Dir is held for drop.
 pub(crate) struct TarPackage<'a>(DirectoryPackage, temp::Dir<'a>);
@@ -534,6 +613,12 @@ fn unpack_without_first_dir<R: Read>(
     Ok(())
 }
 
+impl TarPackage<'_> {
+    pub(crate) fn directory(&self) -> &DirectoryPackage {
+        &self.0
+    }
+}
+
 impl<'a> Package for TarPackage<'a> {
     fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
         self.0.contains(component, short_name)
@@ -544,7 +629,7 @@ impl<'a> Package for TarPackage<'a> {
         component: &str,
         short_name: Option<&str>,
         tx: Transaction<'b>,
-    ) -> Result<Transaction<'b>> {
+    ) -> Result<(Transaction<'b>, InstallSummary)> {
         self.0.install(target, component, short_name, tx)
     }
     fn components(&self) -> Vec<String> {
@@ -572,6 +657,12 @@ impl<'a> TarGzPackage<'a> {
     }
 }
 
+impl TarGzPackage<'_> {
+    pub(crate) fn directory(&self) -> &DirectoryPackage {
+        self.0.directory()
+    }
+}
+
 impl<'a> Package for TarGzPackage<'a> {
     fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
         self.0.contains(component, short_name)
@@ -582,7 +673,7 @@ impl<'a> Package for TarGzPackage<'a> {
         component: &str,
         short_name: Option<&str>,
         tx: Transaction<'b>,
-    ) -> Result<Transaction<'b>> {
+    ) -> Result<(Transaction<'b>, InstallSummary)> {
         self.0.install(target, component, short_name, tx)
     }
     fn components(&self) -> Vec<String> {
@@ -610,6 +701,12 @@ impl<'a> TarXzPackage<'a> {
     }
 }
 
+impl TarXzPackage<'_> {
+    pub(crate) fn directory(&self) -> &DirectoryPackage {
+        self.0.directory()
+    }
+}
+
 impl<'a> Package for TarXzPackage<'a> {
     fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
         self.0.contains(component, short_name)
@@ -620,7 +717,7 @@ impl<'a> Package for TarXzPackage<'a> {
         component: &str,
         short_name: Option<&str>,
         tx: Transaction<'b>,
-    ) -> Result<Transaction<'b>> {
+    ) -> Result<(Transaction<'b>, InstallSummary)> {
         self.0.install(target, component, short_name, tx)
     }
     fn components(&self) -> Vec<String> {
@@ -648,6 +745,12 @@ impl<'a> TarZStdPackage<'a> {
     }
 }
 
+impl TarZStdPackage<'_> {
+    pub(crate) fn directory(&self) -> &DirectoryPackage {
+        self.0.directory()
+    }
+}
+
 impl<'a> Package for TarZStdPackage<'a> {
     fn contains(&self, component: &str, short_name: Option<&str>) -> bool {
         self.0.contains(component, short_name)
@@ -658,7 +761,7 @@ impl<'a> Package for TarZStdPackage<'a> {
         component: &str,
         short_name: Option<&str>,
         tx: Transaction<'b>,
-    ) -> Result<Transaction<'b>> {
+    ) -> Result<(Transaction<'b>, InstallSummary)> {
         self.0.install(target, component, short_name, tx)
     }
     fn components(&self) -> Vec<String> {
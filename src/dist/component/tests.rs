@@ -1,9 +1,15 @@
+use std::cell::RefCell;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use crate::currentprocess::TestProcess;
-use crate::dist::component::Transaction;
+use crate::dist::component::{
+    AuditReport, Component, ComponentBuilder, ComponentDiff, ComponentInfo, ComponentPart,
+    Components, FileDiscrepancy, InstallSummary, Transaction, UninstallPolicy,
+};
 use crate::dist::prefix::InstallPrefix;
 use crate::dist::temp;
 use crate::dist::Notification;
@@ -803,3 +809,2361 @@ fn rollback_failure_keeps_going() {
 #[test]
 #[ignore]
 fn intermediate_dir_rollback() {}
+
+#[test]
+fn component_part_decode() {
+    let part = ComponentPart::decode("file:bin/rustc").unwrap();
+    assert_eq!(part.0, "file");
+    assert_eq!(part.1, PathBuf::from("bin/rustc"));
+
+    let part = ComponentPart::decode("dir:lib/rustlib/src").unwrap();
+    assert_eq!(part.0, "dir");
+    assert_eq!(part.1, PathBuf::from("lib/rustlib/src"));
+
+    assert!(ComponentPart::decode("no separator").is_none());
+    assert!(ComponentPart::decode(":x").is_none());
+    assert!(ComponentPart::decode("x:").is_none());
+    assert!(ComponentPart::decode(":").is_none());
+}
+
+#[test]
+fn component_part_round_trip_with_colons() {
+    for line in ["file:weird:name", "file::leading", "dir:a::b:"] {
+        let part = ComponentPart::decode(line).unwrap();
+        assert_eq!(part.encode(), line);
+    }
+
+    let part = ComponentPart::decode("file::leading").unwrap();
+    assert_eq!(part.0, "file");
+    assert_eq!(part.1, PathBuf::from(":leading"));
+
+    let part = ComponentPart("file".to_owned(), PathBuf::from("weird:name"));
+    let decoded = ComponentPart::decode(&part.encode()).unwrap();
+    assert_eq!(decoded.0, "file");
+    assert_eq!(decoded.1, PathBuf::from("weird:name"));
+}
+
+#[test]
+fn open_checked_missing_prefix() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let missing = prefixdir.path().join("does-not-exist");
+
+    let err = Components::open_checked(InstallPrefix::from(missing.clone())).unwrap_err();
+    match err.downcast_ref::<RustupError>() {
+        Some(RustupError::InstallPrefixNotFound(path)) => assert_eq!(*path, missing),
+        _ => panic!("unexpected error: {err}"),
+    }
+
+    // An existing but empty prefix is fine.
+    Components::open_checked(InstallPrefix::from(prefixdir.path())).unwrap();
+}
+
+fn install_components_in_order(names: &[&str], sorted: bool) -> String {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone())
+        .unwrap()
+        .with_sorted_list(sorted);
+
+    for name in names {
+        let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+        let (tx, _) = components.add(name, tx).unwrap().finish(None).unwrap();
+        tx.commit();
+    }
+
+    fs::read_to_string(prefix.manifest_file("components")).unwrap()
+}
+
+#[test]
+fn components_file_sorted() {
+    let a = install_components_in_order(&["rustc", "cargo", "rust-std"], true);
+    let b = install_components_in_order(&["rust-std", "rustc", "cargo"], true);
+    assert_eq!(a, "cargo\nrust-std\nrustc\n");
+    assert_eq!(a, b);
+}
+
+#[test]
+fn components_file_unsorted() {
+    let a = install_components_in_order(&["rustc", "cargo"], false);
+    assert_eq!(a, "rustc\ncargo\n");
+}
+
+#[test]
+fn uninstall_estimate() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    fs::create_dir_all(prefix.path().join("bin")).unwrap();
+    fs::create_dir_all(prefix.path().join("share/foo")).unwrap();
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.path().join("bin/foo"), "0123456789").unwrap();
+    utils::write_file("", &prefix.path().join("share/foo/doc"), "abc").unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "foo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-foo"),
+        "file:bin/foo\ndir:share/foo\nfile:share/foo/doc\n",
+    )
+    .unwrap();
+
+    let component = Components::open(prefix.clone())
+        .unwrap()
+        .find("foo")
+        .unwrap()
+        .unwrap();
+    assert_eq!(component.uninstall_estimate().unwrap(), (2, 10 + 3));
+}
+
+#[test]
+fn uninstall_estimate_counts_dir_contents() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    fs::create_dir_all(prefix.path().join("bin")).unwrap();
+    fs::create_dir_all(prefix.path().join("share/foo/html")).unwrap();
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.path().join("bin/foo"), "0123456789").unwrap();
+    utils::write_file("", &prefix.path().join("share/foo/doc"), "abc").unwrap();
+    utils::write_file("", &prefix.path().join("share/foo/html/index"), "abcd").unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "foo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-foo"),
+        "dir:share/foo\nfile:bin/foo\n",
+    )
+    .unwrap();
+
+    let component = Components::open(prefix.clone())
+        .unwrap()
+        .find("foo")
+        .unwrap()
+        .unwrap();
+    assert_eq!(component.uninstall_estimate().unwrap(), (1, 10 + 3 + 4));
+}
+
+#[test]
+fn uninstall_all_skipping_corrupt_components() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    fs::create_dir_all(prefix.path().join("bin")).unwrap();
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    for name in ["a", "b", "c"] {
+        utils::write_file("", &prefix.path().join("bin").join(name), "").unwrap();
+    }
+    utils::write_file("", &prefix.manifest_file("components"), "a\nb\nc\n").unwrap();
+    utils::write_file("", &prefix.manifest_file("manifest-a"), "file:bin/a\n").unwrap();
+    utils::write_file("", &prefix.manifest_file("manifest-b"), "garbage\n").unwrap();
+    utils::write_file("", &prefix.manifest_file("manifest-c"), "file:bin/c\n").unwrap();
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let err = components
+        .uninstall_all(UninstallPolicy::FailFast, tx, &tp.process)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::CorruptComponent(n)) if n == "b"
+    ));
+    assert!(utils::is_file(prefix.path().join("bin/a")));
+    assert_eq!(components.list().unwrap().len(), 3);
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let (tx, corrupt) = components
+        .uninstall_all(UninstallPolicy::SkipCorrupt, tx, &tp.process)
+        .unwrap();
+    tx.commit();
+
+    assert_eq!(corrupt, ["b"]);
+    assert!(!utils::path_exists(prefix.path().join("bin/a")));
+    assert!(utils::is_file(prefix.path().join("bin/b")));
+    assert!(!utils::path_exists(prefix.path().join("bin/c")));
+    let names = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["b"]);
+}
+
+#[test]
+fn concurrent_uninstalls_are_serialized() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let lockdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let lock_file = lockdir.path().join("components.lock");
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone())
+        .unwrap()
+        .with_lock_file(lock_file.clone());
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/bar"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let results = std::thread::scope(|s| {
+        let uninstall = || -> anyhow::Result<()> {
+            let txdir = tempfile::Builder::new().prefix("rustup").tempdir()?;
+            let tmp_cx = temp::Context::new(
+                txdir.path().to_owned(),
+                DEFAULT_DIST_SERVER,
+                Box::new(|_| ()),
+            );
+            let tp = TestProcess::default();
+            let components = Components::open(prefix.clone())?.with_lock_file(lock_file.clone());
+            let component = components
+                .find("c")?
+                .ok_or_else(|| anyhow::anyhow!("component 'c' is not installed"))?;
+            let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+            component.uninstall(tx, &tp.process, None)?.commit();
+            Ok(())
+        };
+        let handles = [s.spawn(uninstall), s.spawn(uninstall)];
+        handles.map(|h| h.join().unwrap())
+    });
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(components.list().unwrap().is_empty());
+    assert!(!utils::path_exists(prefix.path().join("bin/bar")));
+    assert!(!utils::path_exists(prefix.manifest_file("manifest-c")));
+    assert!(utils::is_file(&lock_file));
+}
+
+#[test]
+fn verify_against_disk() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "bar").unwrap();
+    let srcdocs = srcdir.path().join("docs");
+    fs::create_dir(&srcdocs).unwrap();
+    utils::write_file("", &srcdocs.join("index.html"), "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("bin/a"), &srcpath).unwrap();
+    builder.copy_file(PathBuf::from("bin/b"), &srcpath).unwrap();
+    builder
+        .copy_dir(PathBuf::from("share/doc"), &srcdocs)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let component = components.find("c").unwrap().unwrap();
+    assert!(component.verify_against_disk().unwrap().is_empty());
+
+    fs::remove_file(prefix.path().join("bin/a")).unwrap();
+    fs::remove_file(prefix.path().join("bin/b")).unwrap();
+    fs::create_dir(prefix.path().join("bin/b")).unwrap();
+    assert_eq!(
+        component.verify_against_disk().unwrap(),
+        [
+            FileDiscrepancy::Missing(PathBuf::from("bin/a")),
+            FileDiscrepancy::WrongKind {
+                path: PathBuf::from("bin/b"),
+                expected: "file".to_owned(),
+            },
+        ]
+    );
+    // Nothing was repaired or removed along the way.
+    assert!(!utils::path_exists(prefix.path().join("bin/a")));
+    assert!(utils::is_directory(prefix.path().join("bin/b")));
+    assert!(utils::is_file(prefix.manifest_file("manifest-c")));
+}
+
+#[test]
+fn uninstall_reports_progress_records() {
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("bin/a"), &srcpath).unwrap();
+    builder.copy_file(PathBuf::from("bin/b"), &srcpath).unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let progress = SharedBuf::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process)
+        .with_progress_writer(Box::new(progress.clone()));
+    let component = components.find("c").unwrap().unwrap();
+    component.uninstall(tx, &tp.process, None).unwrap().commit();
+
+    let output = String::from_utf8(progress.0.lock().unwrap().clone()).unwrap();
+    let records = output
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        [
+            serde_json::json!({"op": "uninstall", "path": "bin/b", "done": 1, "total": 2}),
+            serde_json::json!({"op": "uninstall", "path": "bin/a", "done": 2, "total": 2}),
+        ]
+    );
+}
+
+#[test]
+fn link_file_then_uninstall() {
+    let storedir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = storedir.path().join("rustc");
+    utils::write_file("", &srcpath, "rustc").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .link_file(PathBuf::from("bin/rustc"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let linked = prefix.path().join("bin/rustc");
+    assert!(same_file::is_same_file(&srcpath, &linked).unwrap());
+    let component = components.find("c").unwrap().unwrap();
+    assert_eq!(
+        component.parts().unwrap(),
+        [ComponentPart("file".to_owned(), PathBuf::from("bin/rustc"))]
+    );
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    component.uninstall(tx, &tp.process, None).unwrap().commit();
+
+    assert!(!utils::path_exists(&linked));
+    assert_eq!(fs::read_to_string(&srcpath).unwrap(), "rustc");
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/bar"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let snapshot = components.snapshot().unwrap();
+
+    let component = components.find("c").unwrap().unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    component.uninstall(tx, &tp.process, None).unwrap().commit();
+    assert!(components.find("c").unwrap().is_none());
+    utils::write_file("", &prefix.manifest_file("manifest-stray"), "").unwrap();
+
+    snapshot.restore().unwrap();
+    let component = components.find("c").unwrap().unwrap();
+    assert_eq!(
+        component.parts().unwrap(),
+        [ComponentPart("file".to_owned(), PathBuf::from("bin/bar"))]
+    );
+    assert!(!utils::path_exists(prefix.manifest_file("manifest-stray")));
+}
+
+#[test]
+fn finish_cancelled_rolls_back() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder.copy_file(PathBuf::from("bar"), &srcpath).unwrap();
+    cancel.store(true, Ordering::SeqCst);
+
+    let err = builder.finish(Some(&cancel)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::Cancelled)
+    ));
+
+    assert!(!utils::path_exists(prefix.path().join("foo")));
+    assert!(!utils::path_exists(prefix.path().join("bar")));
+    assert!(!utils::path_exists(prefix.manifest_file("manifest-c")));
+    assert!(components.list().unwrap().is_empty());
+}
+
+#[test]
+fn uninstall_cancelled_rolls_back() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder.copy_file(PathBuf::from("bar"), &srcpath).unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    // The components file has already been rewritten when the token is
+    // first checked, so this must be rolled back as well.
+    let cancel = Arc::new(AtomicBool::new(true));
+    let component = components.find("c").unwrap().unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let err = component
+        .uninstall(tx, &tp.process, Some(&cancel))
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::Cancelled)
+    ));
+
+    assert!(utils::is_file(prefix.path().join("foo")));
+    assert!(utils::is_file(prefix.path().join("bar")));
+    assert!(utils::is_file(prefix.manifest_file("manifest-c")));
+    assert!(components.find("c").unwrap().is_some());
+}
+
+#[test]
+fn find_orphans() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcfile = srcdir.path().join("file");
+    utils::write_file("", &srcfile, "").unwrap();
+    let srcsubdir = srcdir.path().join("dir");
+    fs::create_dir(&srcsubdir).unwrap();
+    utils::write_file("", &srcsubdir.join("file"), "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/foo"), &srcfile)
+        .unwrap();
+    builder
+        .copy_dir(PathBuf::from("share/doc/c"), &srcsubdir)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    utils::write_file("", &prefix.path().join("bin/stray"), "").unwrap();
+    fs::create_dir_all(prefix.path().join("lib/rustlib/etc")).unwrap();
+    utils::write_file("", &prefix.path().join("lib/rustlib/etc/stray"), "").unwrap();
+
+    assert_eq!(
+        components.find_orphans().unwrap(),
+        vec![
+            PathBuf::from("bin/stray"),
+            PathBuf::from("lib/rustlib/etc/stray"),
+        ]
+    );
+}
+
+#[test]
+fn diff_components() {
+    let adir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let bdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let a = InstallPrefix::from(adir.path());
+    let b = InstallPrefix::from(bdir.path());
+
+    fs::create_dir_all(a.manifest_dir()).unwrap();
+    fs::create_dir_all(b.manifest_dir()).unwrap();
+    utils::write_file("", &a.manifest_file("components"), "rustc\ncargo\nrls\n").unwrap();
+    utils::write_file("", &b.manifest_file("components"), "miri\ncargo\nrustc\n").unwrap();
+
+    let a = Components::open(a).unwrap();
+    let b = Components::open(b).unwrap();
+    assert_eq!(
+        a.diff(&b).unwrap(),
+        ComponentDiff {
+            only_in_self: vec!["rls".to_owned()],
+            only_in_other: vec!["miri".to_owned()],
+            in_both: vec!["cargo".to_owned(), "rustc".to_owned()],
+        }
+    );
+}
+
+#[test]
+fn list_ignores_blank_lines() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "\nrustc\n\n  \ncargo\n\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix.clone()).unwrap();
+    let names = components
+        .list()
+        .unwrap()
+        .iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["rustc", "cargo"]);
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    assert!(components.add("", tx).is_err());
+    assert_eq!(components.list().unwrap().len(), 2);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn uninstall_async() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let component = components.find("c").unwrap().unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = component
+        .uninstall_async(tx, &tp.process, None)
+        .await
+        .unwrap();
+    tx.commit();
+
+    assert!(!utils::path_exists(prefix.path().join("foo")));
+    assert!(!utils::path_exists(prefix.manifest_file("manifest-c")));
+    assert!(components.find("c").unwrap().is_none());
+}
+
+#[tokio::test]
+async fn uninstall_async_rolls_back() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder
+        .copy_file(PathBuf::from("bar/baz"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let component = components.find("c").unwrap().unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = component
+        .uninstall_async(tx, &tp.process, None)
+        .await
+        .unwrap();
+    drop(tx);
+
+    assert!(utils::is_file(prefix.path().join("foo")));
+    assert!(utils::is_file(prefix.path().join("bar/baz")));
+    assert!(utils::is_file(prefix.manifest_file("manifest-c")));
+    assert!(components.find("c").unwrap().is_some());
+}
+
+#[test]
+fn parts_are_cached_until_manifest_changes() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "c\n").unwrap();
+    let manifest = prefix.manifest_file("manifest-c");
+    utils::write_file("", &manifest, "file:foo\n").unwrap();
+    let modified = fs::metadata(&manifest).unwrap().modified().unwrap();
+
+    let component = Components::open(prefix.clone())
+        .unwrap()
+        .find("c")
+        .unwrap()
+        .unwrap();
+    assert_eq!(component.parts().unwrap()[0].1, PathBuf::from("foo"));
+
+    // Rewrite the manifest but keep its length and modification time: the
+    // second call must be served without reading the file again.
+    utils::write_file("", &manifest, "file:bar\n").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&manifest)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(component.parts().unwrap()[0].1, PathBuf::from("foo"));
+
+    // Once the file is seen to change, it is read again.
+    utils::write_file("", &manifest, "file:bar\nfile:baz\n").unwrap();
+    let parts = component.parts().unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].1, PathBuf::from("bar"));
+}
+
+#[test]
+fn finish_summary() {
+    use std::collections::HashMap;
+
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    // Sizes are only measured for an interested subscriber.
+    let tp = TestProcess::with_vars(HashMap::from([(
+        "RUSTUP_LOG".to_owned(),
+        "install_summary=info".to_owned(),
+    )]));
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcfile = srcdir.path().join("file");
+    utils::write_file("", &srcfile, "0123456789").unwrap();
+    let srcsubdir = srcdir.path().join("dir");
+    fs::create_dir_all(srcsubdir.join("nested")).unwrap();
+    utils::write_file("", &srcsubdir.join("a"), "abc").unwrap();
+    utils::write_file("", &srcsubdir.join("nested/b"), "de").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/foo"), &srcfile)
+        .unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/bar"), &srcfile)
+        .unwrap();
+    builder
+        .copy_dir(PathBuf::from("share/c"), &srcsubdir)
+        .unwrap();
+    let (tx, summary) = builder.finish(None).unwrap();
+    tx.commit();
+
+    assert_eq!(
+        summary,
+        InstallSummary {
+            files: 2,
+            dirs: 1,
+            bytes: 10 + 10 + 3 + 2,
+        }
+    );
+
+    let quiet = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &quiet.process);
+    let mut builder = components.add("d", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/baz"), &srcfile)
+        .unwrap();
+    let (tx, summary) = builder.finish(None).unwrap();
+    tx.commit();
+
+    assert_eq!(
+        summary,
+        InstallSummary {
+            files: 1,
+            dirs: 0,
+            bytes: 0,
+        }
+    );
+}
+
+#[test]
+fn add_validates_component_name() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    for name in ["", "rustc\ncargo", "../rustc", "rust\\std"] {
+        let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+        let err = components.add(name, tx).err().unwrap();
+        match err.downcast_ref::<RustupError>() {
+            Some(RustupError::InvalidComponentName(n)) => assert_eq!(n, name),
+            _ => panic!("unexpected error: {err}"),
+        }
+    }
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let (tx, _) = components
+        .add("rust-std-x86_64-unknown-linux-gnu", tx)
+        .unwrap()
+        .finish(None)
+        .unwrap();
+    tx.commit();
+    assert!(components
+        .find("rust-std-x86_64-unknown-linux-gnu")
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn dry_run_install_makes_no_changes() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let messages = RefCell::new(Vec::new());
+    let notify = |n: Notification<'_>| {
+        if let Notification::DryRun(..) = n {
+            messages.borrow_mut().push(n.to_string());
+        }
+    };
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process).with_dry_run(true);
+
+    let components = Components::open(prefix.clone()).unwrap();
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let mut builder = components.add("mycomponent", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("foo/bar"), &srcpath)
+        .unwrap();
+    let (tx, _) = builder.finish(None).unwrap();
+    tx.commit();
+
+    assert!(!prefix.path().join("foo/bar").exists());
+    assert!(!prefix.manifest_file("components").exists());
+    assert!(components.list().unwrap().is_empty());
+
+    let messages = messages.into_inner();
+    assert!(messages
+        .iter()
+        .any(|m| m.contains("would copy") && m.contains("bar")));
+}
+
+#[test]
+fn list_filtered() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "cargo\nrustc\nrust-std\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let found = components.list_filtered(|n| n == "rustc").unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name(), "rustc");
+
+    assert!(components.list_filtered(|_| false).unwrap().is_empty());
+    assert_eq!(components.list().unwrap().len(), 3);
+}
+
+#[test]
+fn previews_and_non_previews() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "rustc\nrust-analyzer-preview\ncargo\nmiri-preview\npreview-tools\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let names = |list: Vec<Component>| list.iter().map(|c| c.name().to_owned()).collect::<Vec<_>>();
+    assert_eq!(
+        names(components.previews().unwrap()),
+        ["rust-analyzer-preview", "miri-preview"]
+    );
+    assert_eq!(
+        names(components.non_previews().unwrap()),
+        ["rustc", "cargo", "preview-tools"]
+    );
+}
+
+#[test]
+fn uninstall_warns_if_still_listed() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let warnings = RefCell::new(Vec::new());
+    let notify = |n: Notification<'_>| {
+        if let Notification::ComponentStillListed(_) = n {
+            warnings.borrow_mut().push(n.to_string());
+        }
+    };
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components
+        .add("c", tx)
+        .unwrap()
+        .finish(None)
+        .unwrap()
+        .0
+        .commit();
+    let component = components.find("c").unwrap().unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    component.uninstall(tx, &tp.process, None).unwrap().commit();
+    assert!(warnings.borrow().is_empty());
+
+    // Simulate another process re-adding the component behind our back.
+    utils::append_file("", &prefix.manifest_file("components"), "c").unwrap();
+    component.check_uninstalled(&notify).unwrap();
+    assert_eq!(
+        warnings.into_inner(),
+        ["component 'c' is still listed as installed after uninstalling it"]
+    );
+}
+
+#[test]
+fn audit_reports_both_directions() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\n",
+    )
+    .unwrap();
+    // Listed, but `manifest-cargo` is missing; `manifest-rls` is not listed.
+    utils::write_file("", &prefix.manifest_file("manifest-rls"), "file:bin/rls\n").unwrap();
+    utils::write_file("", &prefix.manifest_file("rust-installer-version"), "3").unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let report = components.audit().unwrap();
+    assert_eq!(
+        report,
+        AuditReport {
+            missing_manifests: vec!["cargo".to_owned()],
+            unlisted_manifests: vec!["rls".to_owned()],
+            mismatched_formats: vec![],
+            corrupt_manifests: vec![],
+        }
+    );
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn audit_reports_mixed_manifest_formats() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\ndir:lib/rustlib\n",
+    )
+    .unwrap();
+    // Bare absolute paths, as left behind by an older installer.
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-cargo"),
+        "/usr/local/bin/cargo\n",
+    )
+    .unwrap();
+    utils::write_file("", &prefix.manifest_file("rust-installer-version"), "3").unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let report = components.audit().unwrap();
+    assert_eq!(
+        report,
+        AuditReport {
+            missing_manifests: vec![],
+            unlisted_manifests: vec![],
+            mismatched_formats: vec!["cargo".to_owned()],
+            corrupt_manifests: vec![],
+        }
+    );
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn audit_reports_unparsable_manifests_as_corrupt() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "rustc\ncargo\nrls\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\n",
+    )
+    .unwrap();
+    // Neither format: a current line mixed with a bare path.
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-cargo"),
+        "file:bin/cargo\n/usr/local/bin/cargo\n",
+    )
+    .unwrap();
+    // A kind that no installer writes, rather than a bare path.
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rls"),
+        "socket:bin/rls\n",
+    )
+    .unwrap();
+    utils::write_file("", &prefix.manifest_file("rust-installer-version"), "3").unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let report = components.audit().unwrap();
+    assert_eq!(
+        report,
+        AuditReport {
+            missing_manifests: vec![],
+            unlisted_manifests: vec![],
+            mismatched_formats: vec![],
+            corrupt_manifests: vec!["cargo".to_owned(), "rls".to_owned()],
+        }
+    );
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn rust_version() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let components = Components::open(prefix.clone()).unwrap();
+    assert_eq!(components.rust_version().unwrap(), None);
+
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("multirust-channel-manifest.toml"),
+        include_str!("../manifest/tests/channel-rust-nightly-example.toml"),
+    )
+    .unwrap();
+    assert_eq!(
+        components.rust_version().unwrap().as_deref(),
+        Some("rustc 1.3.0 (9a92aaf19 2015-09-15)")
+    );
+}
+
+#[test]
+fn crlf_line_endings() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "rustc\r\ncargo\r\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\r\ndir:lib/rustlib\r\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let names = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["rustc", "cargo"]);
+
+    let parts = components.find("rustc").unwrap().unwrap().parts().unwrap();
+    assert_eq!(parts[0].1, PathBuf::from("bin/rustc"));
+    assert_eq!(parts[1].1, PathBuf::from("lib/rustlib"));
+
+    // A stray carriage return that `lines` leaves behind.
+    let part = ComponentPart::decode("file:bin/cargo\r").unwrap();
+    assert_eq!(part.1, PathBuf::from("bin/cargo"));
+}
+
+#[test]
+#[cfg(unix)]
+fn add_to_read_only_prefix() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let manifest_dir = prefix.manifest_dir();
+    fs::create_dir_all(&manifest_dir).unwrap();
+    fs::set_permissions(&manifest_dir, fs::Permissions::from_mode(0o555)).unwrap();
+    // Privileged users can write to read-only directories anyway.
+    if fs::write(manifest_dir.join("probe"), "").is_ok() {
+        return;
+    }
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+
+    let components = Components::open(prefix).unwrap();
+    let err = components.add("c", tx).err().unwrap();
+    fs::set_permissions(&manifest_dir, fs::Permissions::from_mode(0o755)).unwrap();
+    match err.downcast_ref::<RustupError>() {
+        Some(RustupError::PrefixNotWritable { path }) => assert_eq!(*path, manifest_dir),
+        _ => panic!("unexpected error: {err}"),
+    }
+}
+
+#[test]
+fn owned_dirs() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+    // `bin` and `lib/rustlib/etc` are shared, `share` and below only hold rustc.
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\ndir:share/doc/rust\nfile:lib/rustlib/etc/gdb_load_rust_pretty_printers.py\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-cargo"),
+        "file:bin/cargo\nfile:lib/rustlib/etc/cargo.py\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let rustc = components.find("rustc").unwrap().unwrap();
+    assert_eq!(
+        rustc.owned_dirs().unwrap(),
+        [
+            PathBuf::from("share"),
+            PathBuf::from("share/doc"),
+            PathBuf::from("share/doc/rust"),
+        ]
+    );
+    let cargo = components.find("cargo").unwrap().unwrap();
+    assert!(cargo.owned_dirs().unwrap().is_empty());
+}
+
+#[test]
+fn parts_by_root() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\nfile:lib/librustc_driver.so\nfile:bin/rustdoc\ndir:lib/rustlib/etc\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let groups = components
+        .find("rustc")
+        .unwrap()
+        .unwrap()
+        .parts_by_root()
+        .unwrap();
+    let groups = groups
+        .iter()
+        .map(|(root, parts)| {
+            let paths = parts.iter().map(|p| p.1.clone()).collect::<Vec<_>>();
+            (root.as_str(), paths)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups,
+        [
+            (
+                "bin",
+                vec![PathBuf::from("bin/rustc"), PathBuf::from("bin/rustdoc")]
+            ),
+            (
+                "lib",
+                vec![
+                    PathBuf::from("lib/librustc_driver.so"),
+                    PathBuf::from("lib/rustlib/etc")
+                ]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn set_list() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\n",
+    )
+    .unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components
+        .set_list(&["rustc", "cargo", "rustc", "rust-std"], tx)
+        .unwrap()
+        .commit();
+
+    let names = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["rustc", "cargo", "rust-std"]);
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("rust-installer-version")).unwrap(),
+        "3"
+    );
+    // Component manifests are untouched.
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("manifest-rustc")).unwrap(),
+        "file:bin/rustc\n"
+    );
+    assert!(!prefix.manifest_file("manifest-cargo").exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn create_symlink_and_roll_back() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+    let link = prefix.path().join("bin/rust-lld");
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .create_symlink(PathBuf::from("bin/rust-lld"), PathBuf::from("../lib/lld"))
+        .unwrap();
+    assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("../lib/lld"));
+    drop(builder);
+    assert!(fs::symlink_metadata(&link).is_err());
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder
+        .create_symlink(PathBuf::from("bin/rust-lld"), PathBuf::from("../lib/lld"))
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+    assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("../lib/lld"));
+
+    let part = &components.find("c").unwrap().unwrap().parts().unwrap()[0];
+    assert_eq!(part.encode(), "symlink:bin/rust-lld");
+}
+
+#[test]
+fn read_only_components_refuse_changes() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder.finish(None).unwrap().0.commit();
+    let before = fs::read_to_string(prefix.manifest_file("components")).unwrap();
+
+    let components = Components::open_read_only(prefix.clone()).unwrap();
+    assert!(components.is_read_only());
+    let component = components.find("c").unwrap().unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let err = component.uninstall(tx, &tp.process, None).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::ComponentsReadOnly { .. })
+    ));
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    assert!(components.add("d", tx).is_err());
+
+    assert!(utils::is_file(prefix.path().join("foo")));
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("components")).unwrap(),
+        before
+    );
+}
+
+#[test]
+fn components_with_custom_filename() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let default = Components::open(prefix.clone()).unwrap();
+    let custom = Components::open_with_filename(prefix.clone(), "components-alt").unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = custom.add("c", tx).unwrap();
+    builder.copy_file(PathBuf::from("foo"), &srcpath).unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("components-alt")).unwrap(),
+        "c\n"
+    );
+    assert!(!prefix.manifest_file("components").exists());
+    assert_eq!(custom.list().unwrap().len(), 1);
+    assert!(default.list().unwrap().is_empty());
+
+    assert!(Components::open_with_filename(prefix.clone(), "a/b").is_err());
+}
+
+#[test]
+fn rollback_reports_each_operation() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    utils::write_file("", &prefix.path().join("c"), "").unwrap();
+
+    let messages = RefCell::new(Vec::new());
+    let notify = |n: Notification<'_>| {
+        if let Notification::RolledBack(..) = n {
+            messages.borrow_mut().push(n.to_string());
+        }
+    };
+    let tp = TestProcess::default();
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    tx.write_file("c", PathBuf::from("a"), String::new())
+        .unwrap();
+    tx.write_file("c", PathBuf::from("b"), String::new())
+        .unwrap();
+    tx.remove_file("c", PathBuf::from("c")).unwrap();
+    drop(tx);
+
+    assert_eq!(
+        *messages.borrow(),
+        [
+            "rolled back removed file 'c'",
+            "rolled back added file 'b'",
+            "rolled back added file 'a'",
+        ]
+    );
+    assert!(utils::is_file(prefix.path().join("c")));
+}
+
+#[test]
+fn install_many_shares_one_transaction() {
+    use std::collections::HashMap;
+
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "bar").unwrap();
+
+    let messages = RefCell::new(Vec::new());
+    let notify = |n: Notification<'_>| match n {
+        Notification::DryRun(..) | Notification::ComponentBatchProgress(..) => {
+            messages.borrow_mut().push(n.to_string())
+        }
+        _ => {}
+    };
+    let tp = TestProcess::with_vars(HashMap::from([(
+        "RUSTUP_LOG".to_owned(),
+        "install_summary=info".to_owned(),
+    )]));
+    let components = Components::open(prefix.clone()).unwrap();
+    fn batch<'a>(
+        components: &Components,
+        srcpath: &Path,
+        tx: Transaction<'a>,
+    ) -> anyhow::Result<(Transaction<'a>, InstallSummary)> {
+        let batch = ["a", "b", "c"].map(|name| {
+            (name, move |b: &mut ComponentBuilder<'_>| {
+                b.copy_file(PathBuf::from(format!("lib/{name}")), srcpath)
+            })
+        });
+        components.install_many(batch, tx, None)
+    }
+
+    // A dry run reports every operation, so the metadata writes can be
+    // counted.
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process).with_dry_run(true);
+    batch(&components, &srcpath, tx).unwrap().0.commit();
+    let count = |needle: &str| {
+        messages
+            .borrow()
+            .iter()
+            .filter(|m| m.contains(needle))
+            .count()
+    };
+    assert_eq!(count("rust-installer-version"), 1);
+    assert_eq!(count("would modify") - count("rust-installer-version"), 1);
+    assert_eq!(count("installed 3 of 3 components"), 1);
+    messages.borrow_mut().clear();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let (tx, summary) = batch(&components, &srcpath, tx).unwrap();
+    tx.commit();
+    assert_eq!(summary.files, 3);
+    assert_eq!(summary.bytes, 9);
+
+    let names = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["a", "b", "c"]);
+    for name in names {
+        assert!(utils::is_file(prefix.path().join("lib").join(&name)));
+        assert!(utils::is_file(
+            prefix.manifest_file(&format!("manifest-{name}"))
+        ));
+    }
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("rust-installer-version")).unwrap(),
+        "3"
+    );
+    assert_eq!(
+        *messages.borrow(),
+        [
+            "installed 1 of 3 components",
+            "installed 2 of 3 components",
+            "installed 3 of 3 components",
+        ]
+    );
+}
+
+#[test]
+fn expected_version_matches_version_file() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let builder = components.add("rustc", tx).unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    assert_eq!(
+        fs::read_to_string(prefix.manifest_file("rust-installer-version")).unwrap(),
+        Components::expected_version()
+    );
+    // A prefix stamped with the expected version opens fine.
+    assert!(Components::open(prefix).is_ok());
+}
+
+#[test]
+fn install_many_installs_critical_components_first() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let order = RefCell::new(Vec::new());
+    let batch = [
+        "rust-docs",
+        "clippy-preview",
+        "cargo",
+        "rust-std-x86_64-unknown-linux-gnu",
+        "rustc-dev",
+        "rustc",
+    ]
+    .map(|name| {
+        let order = &order;
+        (
+            name,
+            move |_: &mut ComponentBuilder<'_>| -> anyhow::Result<()> {
+                order.borrow_mut().push(name);
+                Ok(())
+            },
+        )
+    });
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components.install_many(batch, tx, None).unwrap().0.commit();
+
+    assert_eq!(
+        order.into_inner(),
+        [
+            "rustc",
+            "rust-std-x86_64-unknown-linux-gnu",
+            "cargo",
+            "rust-docs",
+            "clippy-preview",
+            "rustc-dev",
+        ]
+    );
+}
+
+#[test]
+fn install_many_refuses_paths_claimed_within_batch() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("tool");
+    utils::write_file("", &srcpath, "").unwrap();
+    let batch = ["a", "b"].map(|name| {
+        let srcpath = &srcpath;
+        (name, move |builder: &mut ComponentBuilder<'_>| {
+            builder.copy_file(PathBuf::from("bin/tool"), srcpath)
+        })
+    });
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let err = components.install_many(batch, tx, None).unwrap_err();
+    match err.downcast_ref::<RustupError>() {
+        Some(RustupError::ComponentPathOwned { name, owner, .. }) => {
+            assert_eq!(name, "b");
+            assert_eq!(owner, "a");
+        }
+        _ => panic!("{err}"),
+    }
+    assert!(components.list().unwrap().is_empty());
+}
+
+#[test]
+fn components_to_json() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\ndir:lib/rustlib/etc\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-cargo"),
+        "file:bin/cargo\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix.clone()).unwrap();
+    let json = components.to_json().unwrap();
+    let infos: Vec<ComponentInfo> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        infos,
+        [
+            ComponentInfo {
+                name: "rustc".to_owned(),
+                manifest_path: prefix.manifest_file("manifest-rustc"),
+                part_count: 2,
+            },
+            ComponentInfo {
+                name: "cargo".to_owned(),
+                manifest_path: prefix.manifest_file("manifest-cargo"),
+                part_count: 1,
+            },
+        ]
+    );
+
+    let empty = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let components = Components::open(InstallPrefix::from(empty.path())).unwrap();
+    assert_eq!(components.to_json().unwrap(), "[]");
+}
+
+#[test]
+fn open_recovers_interrupted_list_rewrite() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let list = prefix.manifest_file("components");
+    let staged = prefix.manifest_file("components.new");
+
+    // Crash after the new list was (partially) staged, before the rename:
+    // the old list stays.
+    utils::write_file("", &list, "rustc\ncargo\n").unwrap();
+    utils::write_file("", &staged, "rus").unwrap();
+    let components = Components::open(prefix.clone()).unwrap();
+    assert!(!staged.exists());
+    assert_eq!(fs::read_to_string(&list).unwrap(), "rustc\ncargo\n");
+    assert_eq!(components.list().unwrap().len(), 2);
+
+    // Only the staged list is left: it is moved into place.
+    fs::remove_file(&list).unwrap();
+    utils::write_file("", &staged, "rustc\n").unwrap();
+    let components = Components::open(prefix.clone()).unwrap();
+    assert!(!staged.exists());
+    assert_eq!(fs::read_to_string(&list).unwrap(), "rustc\n");
+    assert_eq!(components.list().unwrap().len(), 1);
+
+    // Read-only access leaves the leftover alone.
+    utils::write_file("", &staged, "").unwrap();
+    Components::open_read_only(prefix.clone()).unwrap();
+    assert!(staged.exists());
+}
+
+#[test]
+fn open_leaves_staged_list_of_locked_uninstall_alone() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let list = prefix.manifest_file("components");
+    let staged = prefix.manifest_file("components.new");
+    utils::write_file("", &list, "rustc\ncargo\n").unwrap();
+    utils::write_file("", &staged, "rustc\n").unwrap();
+
+    // An uninstallation in another process is staging the new list.
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(prefix.manifest_file(".components.lock"))
+        .unwrap();
+    assert!(utils_raw::lock_exclusive(&lock, false).unwrap());
+    Components::open(prefix.clone()).unwrap();
+    assert!(staged.exists());
+    assert_eq!(fs::read_to_string(&list).unwrap(), "rustc\ncargo\n");
+
+    // Once it is gone, the leftover is cleaned up.
+    drop(lock);
+    Components::open(prefix.clone()).unwrap();
+    assert!(!staged.exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn copy_with_permission_policy() {
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::currentprocess::Process;
+    use crate::dist::component::PermissionPolicy;
+
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+    let data = srcdir.path().join("data");
+    utils::write_file("", &data, "").unwrap();
+    fs::set_permissions(&data, fs::Permissions::from_mode(0o600)).unwrap();
+    let moved = srcdir.path().join("moved");
+    utils::write_file("", &moved, "").unwrap();
+    fs::set_permissions(&moved, fs::Permissions::from_mode(0o600)).unwrap();
+    let dir = srcdir.path().join("dir");
+    utils::write_file("", &dir.join("sub/bin"), "").unwrap();
+    fs::set_permissions(dir.join("sub/bin"), fs::Permissions::from_mode(0o700)).unwrap();
+    fs::set_permissions(dir.join("sub"), fs::Permissions::from_mode(0o700)).unwrap();
+
+    // Test processes only record the policy, so apply it for real.
+    let notify = |_: Notification<'_>| ();
+    let process = Process::os();
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &process).with_permissions(
+        PermissionPolicy {
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+        },
+    );
+    tx.copy_file("c", PathBuf::from("data"), &data).unwrap();
+    tx.move_file("c", PathBuf::from("moved"), &moved).unwrap();
+    tx.copy_dir("c", PathBuf::from("dir"), &dir).unwrap();
+    tx.commit();
+
+    assert_eq!(mode(prefix.path().join("data")), 0o640);
+    assert_eq!(mode(prefix.path().join("moved")), 0o640);
+    assert_eq!(mode(prefix.path().join("dir")), 0o750);
+    assert_eq!(mode(prefix.path().join("dir/sub")), 0o750);
+    // Executables stay executable for whoever can read them.
+    assert_eq!(mode(prefix.path().join("dir/sub/bin")), 0o750);
+}
+
+#[test]
+fn permission_policy_from_env_is_recorded() {
+    use std::collections::HashMap;
+
+    use crate::dist::component::PermissionPolicy;
+
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let data = srcdir.path().join("data");
+    utils::write_file("", &data, "").unwrap();
+    let dir = srcdir.path().join("dir");
+    utils::write_file("", &dir.join("file"), "").unwrap();
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::with_vars(HashMap::from([
+        ("RUSTUP_FILE_MODE".to_owned(), "640".to_owned()),
+        ("RUSTUP_DIR_MODE".to_owned(), "0750".to_owned()),
+    ]));
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    tx.copy_file("c", PathBuf::from("data"), &data).unwrap();
+    tx.move_dir("c", PathBuf::from("dir"), &dir).unwrap();
+    tx.commit();
+
+    let policy = PermissionPolicy {
+        file_mode: Some(0o640),
+        dir_mode: Some(0o750),
+    };
+    assert_eq!(
+        tp.recorded_permissions(),
+        [
+            (prefix.path().join("data"), policy),
+            (prefix.path().join("dir"), policy),
+        ]
+    );
+}
+
+#[test]
+fn components_total_size() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let write = |path: &str, content: &str| {
+        let path = prefix.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        utils::write_file("", &path, content).unwrap();
+    };
+    write("bin/rustc", "12345");
+    write("lib/rustlib/etc/a", "123");
+    write("lib/rustlib/etc/b", "12");
+    write("bin/cargo", "1234567");
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\ndir:lib/rustlib/etc\n",
+    )
+    .unwrap();
+    // `cargo` shares the directory, lists a file inside it and one that
+    // has gone missing.
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-cargo"),
+        "file:bin/cargo\ndir:lib/rustlib/etc\nfile:lib/rustlib/etc/a\nfile:bin/gone\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix.clone()).unwrap();
+    assert_eq!(components.total_size().unwrap(), 5 + 3 + 2 + 7);
+}
+
+#[test]
+fn overlapping_components_are_reported() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "first").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("a", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/tool"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("b", tx).unwrap();
+    let err = builder
+        .copy_file(PathBuf::from("bin/tool"), &srcpath)
+        .unwrap_err();
+    match err.downcast_ref::<RustupError>() {
+        Some(RustupError::ComponentPathOwned { name, owner, path }) => {
+            assert_eq!(name, "b");
+            assert_eq!(owner, "a");
+            assert_eq!(path, &PathBuf::from("bin/tool"));
+        }
+        _ => panic!("{err}"),
+    }
+    drop(builder);
+
+    assert_eq!(
+        components.owner_of(&PathBuf::from("bin/tool")).unwrap(),
+        Some("a".to_owned())
+    );
+    assert_eq!(
+        fs::read_to_string(prefix.path().join("bin/tool")).unwrap(),
+        "first"
+    );
+}
+
+#[test]
+fn open_part_reads_in_chunks() {
+    use std::io::Read;
+
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let content = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs::create_dir_all(prefix.path().join("bin")).unwrap();
+    fs::write(prefix.path().join("bin/rustc"), &content).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\ndir:bin\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let component = components.find("rustc").unwrap().unwrap();
+    let parts = component.parts().unwrap();
+
+    let mut reader = component.open_part(&parts[0]).unwrap();
+    let mut read = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let n = reader.read(&mut chunk).unwrap();
+        if n == 0 {
+            break;
+        }
+        read.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(read, content);
+
+    let err = component.open_part(&parts[1]).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn components_for_current_toolchain() {
+    use std::collections::HashMap;
+
+    let home = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let toolchain = home
+        .path()
+        .join("toolchains/stable-x86_64-unknown-linux-gnu");
+    let prefix = InstallPrefix::from(toolchain.clone());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc\ncargo\n").unwrap();
+
+    let vars = |toolchain: &str| {
+        HashMap::from([
+            (
+                "RUSTUP_HOME".to_owned(),
+                home.path().to_string_lossy().into_owned(),
+            ),
+            ("RUSTUP_TOOLCHAIN".to_owned(), toolchain.to_owned()),
+        ])
+    };
+
+    let tp = TestProcess::with_vars(vars("stable-x86_64-unknown-linux-gnu"));
+    let components = Components::for_current_toolchain(&tp.process).unwrap();
+    assert_eq!(components.list().unwrap().len(), 2);
+
+    // Custom toolchains are referred to by path.
+    let tp = TestProcess::with_vars(vars(&toolchain.to_string_lossy()));
+    let components = Components::for_current_toolchain(&tp.process).unwrap();
+    assert!(components.find("cargo").unwrap().is_some());
+
+    let tp = TestProcess::with_vars(vars("nightly"));
+    let err = Components::for_current_toolchain(&tp.process).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::InstallPrefixNotFound(_))
+    ));
+
+    let err = Components::for_current_toolchain(&TestProcess::default().process).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::NoCurrentToolchain)
+    ));
+}
+
+#[test]
+fn rename_component() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    for name in ["rustc", "rust-analyzer-preview", "cargo"] {
+        let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+        let mut builder = components.add(name, tx).unwrap();
+        builder
+            .copy_file(PathBuf::from(format!("bin/{name}")), &srcpath)
+            .unwrap();
+        builder.finish(None).unwrap().0.commit();
+    }
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components
+        .rename("rust-analyzer-preview", "rust-analyzer", tx)
+        .unwrap()
+        .commit();
+
+    let names = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["rustc", "rust-analyzer", "cargo"]);
+    assert!(!prefix
+        .manifest_file("manifest-rust-analyzer-preview")
+        .exists());
+    let component = components.find("rust-analyzer").unwrap().unwrap();
+    assert_eq!(
+        component.parts().unwrap()[0].encode(),
+        "file:bin/rust-analyzer-preview"
+    );
+    assert!(utils::is_file(
+        prefix.path().join("bin/rust-analyzer-preview")
+    ));
+
+    // Renaming over an installed component is refused.
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    assert!(components.rename("rust-analyzer", "cargo", tx).is_err());
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    assert!(components.rename("miri", "miri-preview", tx).is_err());
+}
+
+#[test]
+fn rename_refuses_listed_name_with_separator() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file("", &prefix.manifest_file("components"), "rustc/bin\n").unwrap();
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let err = components.rename("rustc/bin", "rustc", tx).err().unwrap();
+    match err.downcast_ref::<RustupError>() {
+        Some(RustupError::ComponentManifestNameHasSeparator(n)) => assert_eq!(n, "rustc/bin"),
+        _ => panic!("unexpected error: {err}"),
+    }
+    assert!(!prefix.manifest_file("manifest-rustc").exists());
+}
+
+#[test]
+fn owner_of_and_owner_index() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("components"),
+        "rustc\nrust-docs\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rustc"),
+        "file:bin/rustc\nfile:lib/librustc_driver.so\n",
+    )
+    .unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-rust-docs"),
+        "dir:share/doc/rust/html\n",
+    )
+    .unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    let owner = |path: &str| components.owner_of(Path::new(path)).unwrap();
+    assert_eq!(owner("bin/rustc").as_deref(), Some("rustc"));
+    assert_eq!(
+        owner("share/doc/rust/html/std/index.html").as_deref(),
+        Some("rust-docs")
+    );
+    assert_eq!(owner("bin/cargo"), None);
+    assert_eq!(owner("share/doc/rust"), None);
+
+    let index = components.owner_index().unwrap();
+    assert_eq!(
+        index.owner_of(Path::new("lib/librustc_driver.so")),
+        Some("rustc")
+    );
+    assert_eq!(
+        index.owner_of(Path::new("share/doc/rust/html")),
+        Some("rust-docs")
+    );
+    assert_eq!(index.owner_of(Path::new("lib")), None);
+}
+
+#[test]
+fn remove_some_parts() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("rustc", tx).unwrap();
+    for path in ["bin/rustc", "bin/rustdoc", "bin/rust-gdb"] {
+        builder.copy_file(PathBuf::from(path), &srcpath).unwrap();
+    }
+    builder.finish(None).unwrap().0.commit();
+
+    let component = components.find("rustc").unwrap().unwrap();
+    let rustdoc = ComponentPart("file".to_owned(), PathBuf::from("bin/rustdoc"));
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    component
+        .remove_parts(&[rustdoc.clone()], tx)
+        .unwrap()
+        .commit();
+
+    assert!(!utils::path_exists(prefix.path().join("bin/rustdoc")));
+    assert!(utils::is_file(prefix.path().join("bin/rustc")));
+    let parts = components.find("rustc").unwrap().unwrap().parts().unwrap();
+    assert_eq!(
+        parts.iter().map(ComponentPart::encode).collect::<Vec<_>>(),
+        ["file:bin/rustc", "file:bin/rust-gdb"]
+    );
+
+    // Parts that aren't in the manifest are refused, and nothing is removed.
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    assert!(component
+        .remove_parts(&[parts[1].clone(), rustdoc], tx)
+        .is_err());
+    assert!(utils::is_file(prefix.path().join("bin/rust-gdb")));
+}
+
+#[test]
+fn ensure_missing_version() {
+    let srcdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let components = Components::open(prefix.clone()).unwrap();
+
+    let srcpath = srcdir.path().join("bar");
+    utils::write_file("", &srcpath, "").unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut builder = components.add("rustc", tx).unwrap();
+    builder
+        .copy_file(PathBuf::from("bin/rustc"), &srcpath)
+        .unwrap();
+    builder.finish(None).unwrap().0.commit();
+
+    let version_file = prefix.manifest_file("rust-installer-version");
+    fs::remove_file(&version_file).unwrap();
+
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components.ensure_version(tx).unwrap().commit();
+    assert_eq!(utils::read_file("", &version_file).unwrap(), "3");
+
+    // An existing version file is left alone.
+    utils::write_file("", &version_file, "3\n").unwrap();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    components.ensure_version(tx).unwrap().commit();
+    assert_eq!(utils::read_file("", &version_file).unwrap(), "3\n");
+    assert!(components.find("rustc").unwrap().is_some());
+}
+
+#[test]
+fn count_large_components_file() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    fs::create_dir_all(prefix.manifest_dir()).unwrap();
+
+    let mut list = (0..10_000)
+        .map(|i| format!("component-{i}\n"))
+        .collect::<String>();
+    // Blank lines and CRLF line endings don't count as extra components.
+    list.push_str("\n  \ncomponent-crlf\r\n");
+    utils::write_file("", &prefix.manifest_file("components"), &list).unwrap();
+
+    let components = Components::open(prefix).unwrap();
+    assert_eq!(components.count().unwrap(), 10_001);
+    assert_eq!(
+        components.count().unwrap(),
+        components.list().unwrap().len()
+    );
+
+    let names = components
+        .iter()
+        .unwrap()
+        .map(|c| c.unwrap().name().to_owned())
+        .collect::<Vec<_>>();
+    let listed = components
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, listed);
+    assert_eq!(names.last().unwrap(), "component-crlf");
+}
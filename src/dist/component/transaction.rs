@@ -10,6 +10,7 @@
 //! does not remove any dirs created by it.
 
 use std::fs::File;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
@@ -19,7 +20,7 @@ use crate::dist::notifications::*;
 use crate::dist::prefix::InstallPrefix;
 use crate::dist::temp;
 use crate::errors::*;
-use crate::utils::utils;
+use crate::utils::utils::{self, FsRetry};
 
 /// A Transaction tracks changes to the file system, allowing them to
 /// be rolled back in case of an error. Instead of deleting or
@@ -34,13 +35,51 @@ use crate::utils::utils;
 ///
 /// All operations that create files will fail if the destination
 /// already exists.
+///
+/// In dry-run mode, no operation touches the install prefix: each one is
+/// reported through the notification handler instead.
 pub struct Transaction<'a> {
     prefix: InstallPrefix,
     changes: Vec<ChangedItem<'a>>,
     tmp_cx: &'a temp::Context,
     notify_handler: &'a dyn Fn(Notification<'_>),
     committed: bool,
+    dry_run: bool,
+    permissions: PermissionPolicy,
+    fs_retry: FsRetry,
     process: &'a Process,
+    /// The lock files held until the transaction is committed or rolled
+    /// back, by path.
+    locks: Vec<(PathBuf, File)>,
+    /// Where to write machine-readable progress records, if anywhere.
+    progress: Option<Box<dyn Write>>,
+}
+
+/// Explicit permissions for what a [`Transaction`] copies or moves into the
+/// prefix, instead of whatever the umask yields. This only has an effect on
+/// Unix, and test processes only record it.
+///
+/// Executable files additionally get an execute bit for each read bit of
+/// `file_mode`, so that `0o644` becomes `0o755` for binaries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PermissionPolicy {
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+}
+
+impl PermissionPolicy {
+    /// Reads the octal modes in `RUSTUP_FILE_MODE` and `RUSTUP_DIR_MODE`,
+    /// leaving out those that are unset or invalid.
+    pub(crate) fn from_process(process: &Process) -> Self {
+        let var = |key| {
+            let value = process.var(key).ok()?;
+            u32::from_str_radix(value.trim(), 8).ok()
+        };
+        Self {
+            file_mode: var("RUSTUP_FILE_MODE"),
+            dir_mode: var("RUSTUP_DIR_MODE"),
+        }
+    }
 }
 
 impl<'a> Transaction<'a> {
@@ -56,8 +95,76 @@ impl<'a> Transaction<'a> {
             tmp_cx,
             notify_handler,
             committed: false,
+            dry_run: false,
+            permissions: PermissionPolicy::from_process(process),
+            fs_retry: FsRetry::from_process(process),
             process,
+            locks: Vec::new(),
+            progress: progress_writer(process),
+        }
+    }
+
+    /// Writes progress records to `writer` instead of the file descriptor
+    /// named by `RUSTUP_PROGRESS_FD`.
+    pub fn with_progress_writer(mut self, writer: Box<dyn Write>) -> Self {
+        self.progress = Some(writer);
+        self
+    }
+
+    /// Reports that `done` of the `total` parts of an `op`, e.g.
+    /// `"uninstall"`, have been processed, the last one being `path`.
+    ///
+    /// Each record is a line of JSON. Failing to write it doesn't fail the
+    /// operation.
+    pub(crate) fn report_progress(&mut self, op: &str, path: &Path, done: usize, total: usize) {
+        let Some(writer) = &mut self.progress else {
+            return;
+        };
+        let record = serde_json::json!({
+            "op": op,
+            "path": path.to_string_lossy(),
+            "done": done,
+            "total": total,
+        });
+        let _ = writeln!(writer, "{record}").and_then(|()| writer.flush());
+    }
+
+    /// Switches the transaction to dry-run mode.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets the permissions of the files and directories copied or moved
+    /// from now on, instead of those from `RUSTUP_FILE_MODE` and
+    /// `RUSTUP_DIR_MODE`.
+    pub fn with_permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Whether the transaction holds the lock file at `path`.
+    pub(crate) fn holds_lock(&self, path: &Path) -> bool {
+        self.locks.iter().any(|(p, _)| p == path)
+    }
+
+    /// Keeps the locked `file` open, and thereby locked, until the
+    /// transaction is committed or has been rolled back.
+    pub(crate) fn hold_lock(&mut self, path: PathBuf, file: File) {
+        self.locks.push((path, file));
+    }
+
+    /// In dry-run mode, reports `action` on `relpath` and returns `true` to
+    /// signal that the operation must be skipped.
+    fn skip_for_dry_run(&self, action: &str, relpath: &Path) -> bool {
+        if self.dry_run {
+            (self.notify_handler)(Notification::DryRun(action, relpath));
         }
+        self.dry_run
     }
 
     /// Commit must be called for all successful transactions. If not
@@ -70,11 +177,35 @@ impl<'a> Transaction<'a> {
         self.changes.push(item);
     }
 
+    /// Applies the permission policy to `path` and everything below it, or
+    /// only records it for a test process.
+    fn apply_permissions(&self, path: &Path) -> Result<()> {
+        if self.permissions == PermissionPolicy::default() {
+            return Ok(());
+        }
+        match self.process {
+            Process::OSProcess(_) => apply_permissions(path, &self.permissions),
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => {
+                let mut recorded = p.permissions.lock().unwrap_or_else(|e| e.into_inner());
+                recorded.push((path.to_owned(), self.permissions));
+                Ok(())
+            }
+        }
+    }
+
     /// Add a file at a relative path to the install prefix. Returns a
     /// `File` that may be used to subsequently write the
     /// contents.
+    ///
+    /// In dry-run mode, the returned `File` is a temporary one.
     pub fn add_file(&mut self, component: &str, relpath: PathBuf) -> Result<File> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("add", &relpath) {
+            let path = self.tmp_cx.new_file()?;
+            return File::create(&path)
+                .with_context(|| format!("error creating file '{}'", path.display()));
+        }
         let (item, file) = ChangedItem::add_file(&self.prefix, component, relpath)?;
         self.change(item);
         Ok(file)
@@ -83,7 +214,54 @@ impl<'a> Transaction<'a> {
     /// Copy a file to a relative path of the install prefix.
     pub fn copy_file(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
-        let item = ChangedItem::copy_file(&self.prefix, component, relpath, src)?;
+        if self.skip_for_dry_run("copy", &relpath) {
+            return Ok(());
+        }
+        let abs_path = self.prefix.abs_path(&relpath);
+        let item = ChangedItem::copy_file(&self.prefix, component, relpath, src, &self.fs_retry)?;
+        self.change(item);
+        self.apply_permissions(&abs_path)
+    }
+
+    /// Hardlinks `src` to a relative path of the install prefix, falling
+    /// back to a copy if that fails, e.g. across devices.
+    ///
+    /// Linked files share their permissions with `src`, so the permission
+    /// policy only applies to copies.
+    pub(crate) fn link_file(
+        &mut self,
+        component: &str,
+        relpath: PathBuf,
+        src: &Path,
+    ) -> Result<()> {
+        assert!(relpath.is_relative());
+        if self.skip_for_dry_run("link", &relpath) {
+            return Ok(());
+        }
+        let abs_path = self.prefix.abs_path(&relpath);
+        let (item, linked) =
+            ChangedItem::link_file(&self.prefix, component, relpath, src, &self.fs_retry)?;
+        self.change(item);
+        if linked {
+            return Ok(());
+        }
+        self.apply_permissions(&abs_path)
+    }
+
+    /// Create a symlink to `target` at a relative path of the install prefix.
+    ///
+    /// This fails on Windows, where rustup doesn't create file symlinks.
+    pub(crate) fn create_symlink(
+        &mut self,
+        component: &str,
+        relpath: PathBuf,
+        target: &Path,
+    ) -> Result<()> {
+        assert!(relpath.is_relative());
+        if self.skip_for_dry_run("link", &relpath) {
+            return Ok(());
+        }
+        let item = ChangedItem::create_symlink(&self.prefix, component, relpath, target)?;
         self.change(item);
         Ok(())
     }
@@ -91,14 +269,21 @@ impl<'a> Transaction<'a> {
     /// Recursively copy a directory to a relative path of the install prefix.
     pub fn copy_dir(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("copy", &relpath) {
+            return Ok(());
+        }
+        let abs_path = self.prefix.abs_path(&relpath);
         let item = ChangedItem::copy_dir(&self.prefix, component, relpath, src)?;
         self.change(item);
-        Ok(())
+        self.apply_permissions(&abs_path)
     }
 
     /// Remove a file from a relative path to the install prefix.
     pub fn remove_file(&mut self, component: &str, relpath: PathBuf) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("remove", &relpath) {
+            return Ok(());
+        }
         let item = ChangedItem::remove_file(
             &self.prefix,
             component,
@@ -106,6 +291,7 @@ impl<'a> Transaction<'a> {
             self.tmp_cx,
             self.notify_handler(),
             self.process,
+            &self.fs_retry,
         )?;
         self.change(item);
         Ok(())
@@ -115,6 +301,9 @@ impl<'a> Transaction<'a> {
     /// install prefix.
     pub fn remove_dir(&mut self, component: &str, relpath: PathBuf) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("remove", &relpath) {
+            return Ok(());
+        }
         let item = ChangedItem::remove_dir(
             &self.prefix,
             component,
@@ -122,15 +311,69 @@ impl<'a> Transaction<'a> {
             self.tmp_cx,
             self.notify_handler(),
             self.process,
+            &self.fs_retry,
         )?;
         self.change(item);
         Ok(())
     }
 
+    /// Prepares the removal of a file, or of a directory if `is_dir` is
+    /// set, from a relative path of the install prefix, without touching
+    /// the prefix yet.
+    ///
+    /// This lets the file system work of a removal run on another thread,
+    /// e.g. with [`tokio::task::spawn_blocking`], which the transaction
+    /// can't be sent to: move [`PlannedRemoval::from`] to
+    /// [`PlannedRemoval::to`] there, then hand the removal back to
+    /// [`Transaction::record_removal`] so that it is rolled back with the
+    /// rest of the transaction.
+    pub(crate) fn plan_removal(
+        &self,
+        component: &str,
+        relpath: PathBuf,
+        is_dir: bool,
+    ) -> Result<PlannedRemoval<'a>> {
+        assert!(relpath.is_relative());
+        let from = self.prefix.abs_path(&relpath);
+        if !utils::path_exists(&from) {
+            let (name, path) = (component.to_owned(), relpath);
+            return Err(if is_dir {
+                RustupError::ComponentMissingDir { name, path }
+            } else {
+                RustupError::ComponentMissingFile { name, path }
+            }
+            .into());
+        }
+        let (item, to) = if is_dir {
+            let backup = self.tmp_cx.new_directory()?;
+            let to = backup.join("bk");
+            (ChangedItem::RemovedDir(relpath, backup), to)
+        } else {
+            let backup = self.tmp_cx.new_file()?;
+            let to = backup.to_path_buf();
+            (ChangedItem::RemovedFile(relpath, backup), to)
+        };
+        Ok(PlannedRemoval { item, from, to })
+    }
+
+    /// Records a removal prepared by [`Transaction::plan_removal`] that has
+    /// been carried out.
+    pub(crate) fn record_removal(&mut self, removal: PlannedRemoval<'a>) {
+        self.change(removal.item);
+    }
+
+    /// How the transaction retries transient file system errors.
+    pub(crate) fn fs_retry(&self) -> FsRetry {
+        self.fs_retry
+    }
+
     /// Create a new file with string contents at a relative path to
     /// the install prefix.
     pub fn write_file(&mut self, component: &str, relpath: PathBuf, content: String) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("write", &relpath) {
+            return Ok(());
+        }
         let (item, mut file) = ChangedItem::add_file(&self.prefix, component, relpath.clone())?;
         self.change(item);
         utils::write_str(
@@ -148,6 +391,9 @@ impl<'a> Transaction<'a> {
     /// This is used for arbitrarily manipulating a file.
     pub fn modify_file(&mut self, relpath: PathBuf) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("modify", &relpath) {
+            return Ok(());
+        }
         let item = ChangedItem::modify_file(&self.prefix, relpath, self.tmp_cx)?;
         self.change(item);
         Ok(())
@@ -161,6 +407,10 @@ impl<'a> Transaction<'a> {
         src: &Path,
     ) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("move", &relpath) {
+            return Ok(());
+        }
+        let abs_path = self.prefix.abs_path(&relpath);
         let item = ChangedItem::move_file(
             &self.prefix,
             component,
@@ -168,14 +418,19 @@ impl<'a> Transaction<'a> {
             src,
             self.notify_handler(),
             self.process,
+            &self.fs_retry,
         )?;
         self.change(item);
-        Ok(())
+        self.apply_permissions(&abs_path)
     }
 
     /// Recursively move a directory to a relative path of the install prefix.
     pub(crate) fn move_dir(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
+        if self.skip_for_dry_run("move", &relpath) {
+            return Ok(());
+        }
+        let abs_path = self.prefix.abs_path(&relpath);
         let item = ChangedItem::move_dir(
             &self.prefix,
             component,
@@ -183,9 +438,10 @@ impl<'a> Transaction<'a> {
             src,
             self.notify_handler(),
             self.process,
+            &self.fs_retry,
         )?;
         self.change(item);
-        Ok(())
+        self.apply_permissions(&abs_path)
     }
 
     pub(crate) fn temp(&self) -> &'a temp::Context {
@@ -196,6 +452,36 @@ impl<'a> Transaction<'a> {
     }
 }
 
+/// Opens the file descriptor, or handle on Windows, named by
+/// `RUSTUP_PROGRESS_FD`, leaving the original open for other users.
+fn progress_writer(process: &Process) -> Option<Box<dyn Write>> {
+    let fd = process.var("RUSTUP_PROGRESS_FD").ok()?;
+    let file = open_progress_fd(fd.trim().parse().ok()?).ok()?;
+    Some(Box::new(file))
+}
+
+#[cfg(unix)]
+fn open_progress_fd(fd: i32) -> io::Result<File> {
+    use std::os::fd::BorrowedFd;
+
+    if fd < 0 {
+        return Err(io::Error::from_raw_os_error(libc::EBADF));
+    }
+    // The descriptor is only borrowed long enough to duplicate it,
+    // and the duplicate is closed independently of the original.
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    Ok(File::from(fd.try_clone_to_owned()?))
+}
+
+#[cfg(windows)]
+fn open_progress_fd(handle: isize) -> io::Result<File> {
+    use std::os::windows::io::{BorrowedHandle, RawHandle};
+
+    // As above, the handle is only borrowed to duplicate it.
+    let handle = unsafe { BorrowedHandle::borrow_raw(handle as RawHandle) };
+    Ok(File::from(handle.try_clone_to_owned()?))
+}
+
 /// If a Transaction is dropped without being committed, the changes
 /// are automatically rolled back.
 impl<'a> Drop for Transaction<'a> {
@@ -206,7 +492,9 @@ impl<'a> Drop for Transaction<'a> {
                 // ok_ntfy!(self.notify_handler,
                 //          Notification::NonFatalError,
                 match item.roll_back(&self.prefix, self.notify_handler(), self.process) {
-                    Ok(()) => {}
+                    Ok(()) => {
+                        (self.notify_handler)(Notification::RolledBack(item.kind(), item.path()));
+                    }
                     Err(e) => {
                         (self.notify_handler)(Notification::NonFatalError(&e));
                     }
@@ -216,6 +504,57 @@ impl<'a> Drop for Transaction<'a> {
     }
 }
 
+#[cfg(unix)]
+fn apply_permissions(path: &Path, policy: &PermissionPolicy) -> Result<()> {
+    use std::fs::{self, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = fs::symlink_metadata(path).map_err(|e| RustupError::SettingPermissions {
+        p: path.to_path_buf(),
+        source: e,
+    })?;
+    if metadata.is_dir() {
+        for entry in utils::read_dir("component", path)? {
+            apply_permissions(&entry?.path(), policy)?;
+        }
+        // Last, in case the new mode doesn't let us look inside anymore.
+        if let Some(mode) = policy.dir_mode {
+            utils::set_permissions(path, Permissions::from_mode(mode))?;
+        }
+    } else if metadata.is_file() {
+        if let Some(mode) = policy.file_mode {
+            let mut mode = mode & 0o7777;
+            if metadata.permissions().mode() & 0o111 != 0 {
+                mode |= (mode & 0o444) >> 2;
+            }
+            utils::set_permissions(path, Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::unnecessary_wraps)]
+#[cfg(not(unix))]
+fn apply_permissions(_: &Path, _: &PermissionPolicy) -> Result<()> {
+    Ok(())
+}
+
+/// A removal returned by [`Transaction::plan_removal`].
+pub(crate) struct PlannedRemoval<'a> {
+    item: ChangedItem<'a>,
+    /// The absolute path of what is removed.
+    pub(crate) from: PathBuf,
+    /// The backup location to move it to.
+    pub(crate) to: PathBuf,
+}
+
+impl PlannedRemoval<'_> {
+    /// The path of what is removed, relative to the install prefix.
+    pub(crate) fn relpath(&self) -> &Path {
+        self.item.path()
+    }
+}
+
 /// This is the set of fundamental operations supported on a
 /// Transaction. More complicated operations, such as installing a
 /// package, or updating a component, distill down into a series of
@@ -230,6 +569,26 @@ enum ChangedItem<'a> {
 }
 
 impl<'a> ChangedItem<'a> {
+    fn kind(&self) -> &'static str {
+        use self::ChangedItem::*;
+        match self {
+            AddedFile(_) => "added file",
+            AddedDir(_) => "added directory",
+            RemovedFile(_, _) => "removed file",
+            RemovedDir(_, _) => "removed directory",
+            ModifiedFile(_, _) => "modified file",
+        }
+    }
+    fn path(&self) -> &Path {
+        use self::ChangedItem::*;
+        match self {
+            AddedFile(path)
+            | AddedDir(path)
+            | RemovedFile(path, _)
+            | RemovedDir(path, _)
+            | ModifiedFile(path, _) => path,
+        }
+    }
     fn roll_back(
         &self,
         prefix: &InstallPrefix,
@@ -284,9 +643,35 @@ impl<'a> ChangedItem<'a> {
         component: &str,
         relpath: PathBuf,
         src: &Path,
+        retry: &FsRetry,
+    ) -> Result<Self> {
+        let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
+        retry.run(|| utils::copy_file(src, &abs_path))?;
+        Ok(ChangedItem::AddedFile(relpath))
+    }
+    /// Returns whether the file was linked rather than copied.
+    fn link_file(
+        prefix: &InstallPrefix,
+        component: &str,
+        relpath: PathBuf,
+        src: &Path,
+        retry: &FsRetry,
+    ) -> Result<(Self, bool)> {
+        let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
+        let linked = std::fs::hard_link(src, &abs_path).is_ok();
+        if !linked {
+            retry.run(|| utils::copy_file(src, &abs_path))?;
+        }
+        Ok((ChangedItem::AddedFile(relpath), linked))
+    }
+    fn create_symlink(
+        prefix: &InstallPrefix,
+        component: &str,
+        relpath: PathBuf,
+        target: &Path,
     ) -> Result<Self> {
         let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
-        utils::copy_file(src, &abs_path)?;
+        utils::symlink_file(target, &abs_path)?;
         Ok(ChangedItem::AddedFile(relpath))
     }
     fn copy_dir(
@@ -306,6 +691,7 @@ impl<'a> ChangedItem<'a> {
         tmp_cx: &'a temp::Context,
         notify: &'a dyn Fn(Notification<'_>),
         process: &Process,
+        retry: &FsRetry,
     ) -> Result<Self> {
         let abs_path = prefix.abs_path(&relpath);
         let backup = tmp_cx.new_file()?;
@@ -316,7 +702,7 @@ impl<'a> ChangedItem<'a> {
             }
             .into())
         } else {
-            utils::rename("component", &abs_path, &backup, notify, process)?;
+            retry.run(|| utils::rename("component", &abs_path, &backup, notify, process))?;
             Ok(ChangedItem::RemovedFile(relpath, backup))
         }
     }
@@ -327,6 +713,7 @@ impl<'a> ChangedItem<'a> {
         tmp_cx: &'a temp::Context,
         notify: &'a dyn Fn(Notification<'_>),
         process: &Process,
+        retry: &FsRetry,
     ) -> Result<Self> {
         let abs_path = prefix.abs_path(&relpath);
         let backup = tmp_cx.new_directory()?;
@@ -337,7 +724,8 @@ impl<'a> ChangedItem<'a> {
             }
             .into())
         } else {
-            utils::rename("component", &abs_path, &backup.join("bk"), notify, process)?;
+            let dest = backup.join("bk");
+            retry.run(|| utils::rename("component", &abs_path, &dest, notify, process))?;
             Ok(ChangedItem::RemovedDir(relpath, backup))
         }
     }
@@ -366,9 +754,10 @@ impl<'a> ChangedItem<'a> {
         src: &Path,
         notify: &'a dyn Fn(Notification<'_>),
         process: &Process,
+        retry: &FsRetry,
     ) -> Result<Self> {
         let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
-        utils::rename("component", src, &abs_path, notify, process)?;
+        retry.run(|| utils::rename("component", src, &abs_path, notify, process))?;
         Ok(ChangedItem::AddedFile(relpath))
     }
     fn move_dir(
@@ -378,9 +767,10 @@ impl<'a> ChangedItem<'a> {
         src: &Path,
         notify: &'a dyn Fn(Notification<'_>),
         process: &Process,
+        retry: &FsRetry,
     ) -> Result<Self> {
         let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
-        utils::rename("component", src, &abs_path, notify, process)?;
+        retry.run(|| utils::rename("component", src, &abs_path, notify, process))?;
         Ok(ChangedItem::AddedDir(relpath))
     }
 }
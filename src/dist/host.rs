@@ -0,0 +1,79 @@
+//! The target triple rustup was built for, and the one it is running on.
+//!
+//! The two differ when, for instance, an x86_64 build of rustup runs on an
+//! arm64 machine under emulation.
+
+use crate::currentprocess::Process;
+use crate::dist::TargetTriple;
+
+/// The target triple this binary was built for.
+pub fn build_target() -> TargetTriple {
+    TargetTriple::from_build()
+}
+
+/// The target triple of the machine this binary is running on, as detected
+/// at runtime through OS APIs, or `None` if it could not be determined.
+///
+/// `RUSTUP_OVERRIDE_HOST_TRIPLE` takes precedence over detection.
+pub fn runtime_host(process: &Process) -> Option<TargetTriple> {
+    TargetTriple::from_host(process)
+}
+
+/// Both the build target and the runtime host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostInfo {
+    pub build: TargetTriple,
+    pub runtime: Option<TargetTriple>,
+}
+
+impl HostInfo {
+    pub fn detect(process: &Process) -> Self {
+        Self {
+            build: build_target(),
+            runtime: runtime_host(process),
+        }
+    }
+
+    /// Whether the binary runs natively on the kind of host it was built
+    /// for. Returns `None` if the runtime host is unknown.
+    pub fn is_native(&self) -> Option<bool> {
+        self.runtime
+            .as_ref()
+            .map(|runtime| *runtime == self.build && !TargetTriple::is_host_emulated())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::currentprocess::TestProcess;
+    use crate::dist::PartialTargetTriple;
+
+    #[test]
+    fn build_target_is_known() {
+        let build = build_target();
+        let parsed = PartialTargetTriple::new(&build).unwrap();
+        assert!(parsed.arch.is_some(), "{build}");
+        assert!(parsed.os.is_some(), "{build}");
+    }
+
+    #[test]
+    fn compare_with_overridden_host() {
+        let build = build_target();
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_OVERRIDE_HOST_TRIPLE".to_owned(),
+            build.to_string(),
+        )]));
+        let info = HostInfo::detect(&tp.process);
+        assert_eq!(info.runtime.as_ref(), Some(&build));
+        assert_eq!(info.is_native(), Some(!TargetTriple::is_host_emulated()));
+
+        let tp = TestProcess::with_vars(HashMap::from([(
+            "RUSTUP_OVERRIDE_HOST_TRIPLE".to_owned(),
+            "riscv64gc-unknown-linux-gnu-not-really".to_owned(),
+        )]));
+        assert_eq!(HostInfo::detect(&tp.process).is_native(), Some(false));
+    }
+}
@@ -8,10 +8,11 @@ use std::path::Path;
 
 use anyhow::{anyhow, bail, Context, Result};
 use tokio_retry::{strategy::FixedInterval, RetryIf};
+use tracing::info;
 
 use crate::currentprocess::Process;
 use crate::dist::component::{
-    Components, Package, TarGzPackage, TarXzPackage, TarZStdPackage, Transaction,
+    ComponentBuilder, Components, Package, TarGzPackage, TarXzPackage, TarZStdPackage, Transaction,
 };
 use crate::dist::config::Config;
 use crate::dist::download::{DownloadCfg, File};
@@ -26,10 +27,19 @@ use crate::utils::utils;
 pub(crate) const DIST_MANIFEST: &str = "multirust-channel-manifest.toml";
 pub(crate) const CONFIG_FILE: &str = "multirust-config.toml";
 
+/// The `tracing` target of the event that [`Manifestation::update`] emits once
+/// all components are installed, unless in a dry run. It carries the `toolchain` name and the total
+/// number of `components`, `files`, `dirs` and `bytes` installed.
+///
+/// The target deliberately lies outside of `rustup`, so that the event is not
+/// printed by the console logger but remains available to other subscribers.
+pub const INSTALL_SUMMARY_TARGET: &str = "install_summary";
+
 #[derive(Debug)]
 pub struct Manifestation {
     installation: Components,
     target_triple: TargetTriple,
+    dry_run: bool,
 }
 
 #[derive(Debug)]
@@ -80,9 +90,17 @@ impl Manifestation {
         Ok(Self {
             installation: Components::open(prefix)?,
             target_triple: triple,
+            dry_run: false,
         })
     }
 
+    /// Makes `update` report the changes it would make instead of
+    /// making them. Packages are still downloaded and verified.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     /// Install or update from a given channel manifest, while
     /// selecting extension components to add or remove.
     ///
@@ -205,7 +223,8 @@ impl Manifestation {
             tmp_cx,
             download_cfg.notify_handler,
             download_cfg.process,
-        );
+        )
+        .with_dry_run(self.dry_run);
 
         // If the previous installation was from a v1 manifest we need
         // to uninstall it first.
@@ -233,73 +252,87 @@ impl Manifestation {
             )?;
         }
 
-        // Install components
-        for (component, format, installer_file) in things_to_install {
-            // For historical reasons, the rust-installer component
-            // names are not the same as the dist manifest component
-            // names. Some are just the component name some are the
-            // component name plus the target triple.
-            let pkg_name = component.name_in_manifest();
-            let short_pkg_name = component.short_name_in_manifest();
-            let short_name = component.short_name(new_manifest);
-
-            (download_cfg.notify_handler)(Notification::InstallingComponent(
-                &short_name,
-                &self.target_triple,
-                component.target.as_ref(),
-            ));
+        // Install components, in one batch so that the `components` file is
+        // only rewritten once
+        let installed = things_to_install.len();
+        let batch = things_to_install
+            .into_iter()
+            .map(|(component, format, installer_file)| {
+                // For historical reasons, the rust-installer component
+                // names are not the same as the dist manifest component
+                // names. Some are just the component name some are the
+                // component name plus the target triple.
+                let pkg_name = component.name_in_manifest();
+                let name = pkg_name.clone();
+                let fill = move |builder: &mut ComponentBuilder<'_>| -> Result<()> {
+                    let short_pkg_name = component.short_name_in_manifest();
+                    let short_name = component.short_name(new_manifest);
+
+                    (download_cfg.notify_handler)(Notification::InstallingComponent(
+                        &short_name,
+                        &self.target_triple,
+                        component.target.as_ref(),
+                    ));
 
-            let notification_converter = |notification: crate::utils::Notification<'_>| {
-                (download_cfg.notify_handler)(notification.into());
-            };
-            let gz;
-            let xz;
-            let zst;
-            let reader =
-                utils::FileReaderWithProgress::new_file(&installer_file, &notification_converter)?;
-            let package: &dyn Package = match format {
-                CompressionKind::GZip => {
-                    gz = TarGzPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
-                    )?;
-                    &gz
-                }
-                CompressionKind::XZ => {
-                    xz = TarXzPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
+                    let notification_converter = |notification: crate::utils::Notification<'_>| {
+                        (download_cfg.notify_handler)(notification.into());
+                    };
+                    let gz;
+                    let xz;
+                    let zst;
+                    let reader = utils::FileReaderWithProgress::new_file(
+                        &installer_file,
+                        &notification_converter,
                     )?;
-                    &xz
-                }
-                CompressionKind::ZStd => {
-                    zst = TarZStdPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
-                    )?;
-                    &zst
-                }
-            };
+                    let package = match format {
+                        CompressionKind::GZip => {
+                            gz = TarGzPackage::new(
+                                reader,
+                                tmp_cx,
+                                Some(&notification_converter),
+                                download_cfg.process,
+                            )?;
+                            gz.directory()
+                        }
+                        CompressionKind::XZ => {
+                            xz = TarXzPackage::new(
+                                reader,
+                                tmp_cx,
+                                Some(&notification_converter),
+                                download_cfg.process,
+                            )?;
+                            xz.directory()
+                        }
+                        CompressionKind::ZStd => {
+                            zst = TarZStdPackage::new(
+                                reader,
+                                tmp_cx,
+                                Some(&notification_converter),
+                                download_cfg.process,
+                            )?;
+                            zst.directory()
+                        }
+                    };
 
-            // If the package doesn't contain the component that the
-            // manifest says it does then somebody must be playing a joke on us.
-            if !package.contains(&pkg_name, Some(short_pkg_name)) {
-                return Err(RustupError::CorruptComponent(short_name).into());
-            }
+                    // If the package doesn't contain the component that the
+                    // manifest says it does then somebody must be playing a joke on us.
+                    if !package.contains(&pkg_name, Some(short_pkg_name)) {
+                        return Err(RustupError::CorruptComponent(short_name).into());
+                    }
 
-            tx = package.install(&self.installation, &pkg_name, Some(short_pkg_name), tx)?;
-        }
+                    package.add_parts(builder, &pkg_name, Some(short_pkg_name))
+                };
+                (name, fill)
+            });
+        let summary;
+        (tx, summary) = self.installation.install_many(batch, tx, None)?;
 
         // Install new distribution manifest
         let new_manifest_str = new_manifest.clone().stringify()?;
         tx.modify_file(rel_installed_manifest_path)?;
-        utils::write_file("manifest", &installed_manifest_path, &new_manifest_str)?;
+        if !tx.is_dry_run() {
+            utils::write_file("manifest", &installed_manifest_path, &new_manifest_str)?;
+        }
 
         // Write configuration.
         //
@@ -315,11 +348,27 @@ impl Manifestation {
         let rel_config_path = prefix.rel_manifest_file(CONFIG_FILE);
         let config_path = prefix.path().join(&rel_config_path);
         tx.modify_file(rel_config_path)?;
-        utils::write_file("dist config", &config_path, &config_str)?;
+        if !tx.is_dry_run() {
+            utils::write_file("dist config", &config_path, &config_str)?;
+        }
 
         // End transaction
         tx.commit();
 
+        // Nothing was installed in a dry run
+        if !self.dry_run {
+            info!(
+                target: INSTALL_SUMMARY_TARGET,
+                toolchain = toolchain_str,
+                components = installed,
+                files = summary.files,
+                dirs = summary.dirs,
+                bytes = summary.bytes,
+                "installed {toolchain_str} ({installed} components, {} files)",
+                summary.files,
+            );
+        }
+
         download_cfg.clean(&things_downloaded)?;
 
         Ok(UpdateStatus::Changed)
@@ -370,9 +419,9 @@ impl Manifestation {
         let name = component.name_in_manifest();
         let short_name = component.short_name_in_manifest();
         if let Some(c) = self.installation.find(&name)? {
-            tx = c.uninstall(tx, process)?;
+            tx = c.uninstall(tx, process, None)?;
         } else if let Some(c) = self.installation.find(short_name)? {
-            tx = c.uninstall(tx, process)?;
+            tx = c.uninstall(tx, process, None)?;
         } else {
             notify_handler(Notification::MissingInstalledComponent(
                 &component.short_name(manifest),
@@ -481,12 +530,13 @@ impl Manifestation {
         ));
 
         // Begin transaction
-        let mut tx = Transaction::new(prefix, tmp_cx, notify_handler, process);
+        let mut tx =
+            Transaction::new(prefix, tmp_cx, notify_handler, process).with_dry_run(self.dry_run);
 
         // Uninstall components
         let components = self.installation.list()?;
         for component in components {
-            tx = component.uninstall(tx, process)?;
+            tx = component.uninstall(tx, process, None)?;
         }
 
         // Install all the components in the installer
@@ -499,7 +549,7 @@ impl Manifestation {
             &TarGzPackage::new(reader, tmp_cx, Some(&notification_converter), process)?;
 
         for component in package.components() {
-            tx = package.install(&self.installation, &component, None, tx)?;
+            (tx, _) = package.install(&self.installation, &component, None, tx)?;
         }
 
         // End transaction
@@ -526,7 +576,7 @@ impl Manifestation {
         }
 
         for component in installed_components {
-            tx = component.uninstall(tx, process)?;
+            tx = component.uninstall(tx, process, None)?;
         }
 
         Ok(tx)
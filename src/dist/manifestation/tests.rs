@@ -3,7 +3,7 @@
 #![allow(clippy::type_complexity)]
 
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
     collections::HashMap,
     env, fs,
     path::{Path, PathBuf},
@@ -487,6 +487,18 @@ impl TestContext {
         remove: &[Component],
         force: bool,
         dl_cfg: &DownloadCfg<'_>,
+    ) -> Result<UpdateStatus> {
+        self.update_from_dist_with_dry_run(add, remove, force, dl_cfg, false)
+            .await
+    }
+
+    async fn update_from_dist_with_dry_run(
+        &self,
+        add: &[Component],
+        remove: &[Component],
+        force: bool,
+        dl_cfg: &DownloadCfg<'_>,
+        dry_run: bool,
     ) -> Result<UpdateStatus> {
         // Download the dist manifest and place it into the installation prefix
         let manifest_url = make_manifest_url(&self.url, &self.toolchain)?;
@@ -497,7 +509,8 @@ impl TestContext {
 
         // Read the manifest to update the components
         let trip = self.toolchain.target.clone();
-        let manifestation = Manifestation::open(self.prefix.clone(), trip.clone())?;
+        let manifestation =
+            Manifestation::open(self.prefix.clone(), trip.clone())?.with_dry_run(dry_run);
 
         // TODO on install, need to add profile components (but I guess we shouldn't test that logic here)
         let mut profile_components = manifest.get_profile_components(Profile::Default, &trip)?;
@@ -580,6 +593,88 @@ async fn uninstall_removes_config_file() {
     ));
 }
 
+#[tokio::test]
+async fn initial_install_emits_summary() {
+    use std::sync::Mutex;
+
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    use crate::dist::manifestation::INSTALL_SUMMARY_TARGET;
+
+    #[derive(Default)]
+    struct Fields(HashMap<String, String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    struct Capture(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    impl<S: Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            if event.metadata().target() == INSTALL_SUMMARY_TARGET {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+        }
+    }
+
+    let cx = TestContext::new(None, GZOnly);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let _guard = tracing_subscriber::registry()
+        .with(Capture(events.clone()))
+        .set_default();
+    // Nothing is installed in a dry run, so there is nothing to summarize.
+    cx.update_from_dist_with_dry_run(&[], &[], false, &cx.default_dl_cfg(), true)
+        .await
+        .unwrap();
+    assert!(events.lock().unwrap().is_empty());
+    cx.update_from_dist(&[], &[], false).await.unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let fields = &events[0];
+    assert_eq!(fields["toolchain"], cx.toolchain.manifest_name());
+    // rustc, cargo and rust-std, one file each.
+    assert_eq!(fields["components"], "3");
+    assert_eq!(fields["files"], "3");
+    assert_eq!(fields["dirs"], "0");
+    assert!(fields["bytes"].parse::<u64>().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn initial_install_installs_rustc_first() {
+    let cx = TestContext::new(None, GZOnly);
+    let installing = RefCell::new(Vec::new());
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| {
+            if let Notification::InstallingComponent(name, _, _) = n {
+                installing.borrow_mut().push(name.to_owned());
+            }
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    let installing = installing.into_inner();
+    let position = |prefix: &str| installing.iter().position(|n| n.starts_with(prefix));
+    assert_eq!(position("rustc"), Some(0));
+    assert!(position("rust-std").unwrap() < position("cargo").unwrap());
+}
+
 #[tokio::test]
 async fn upgrade() {
     let cx = TestContext::new(None, GZOnly);
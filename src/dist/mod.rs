@@ -22,6 +22,8 @@ pub(crate) mod config;
 pub mod download;
 use download::DownloadCfg;
 
+pub mod host;
+
 pub mod manifest;
 use manifest::{Component, Manifest as ManifestV2};
 
@@ -38,6 +40,7 @@ pub mod temp;
 
 pub(crate) mod triple;
 pub(crate) use triple::*;
+pub use triple::{is_known_triple, validate_triple, ArchInfo, TripleError};
 
 pub static DEFAULT_DIST_SERVER: &str = "https://static.rust-lang.org";
 
@@ -810,6 +813,8 @@ pub(crate) struct DistOptions<'a> {
     pub(crate) components: &'a [&'a str],
     /// Extra targets to install from dist
     pub(crate) targets: &'a [&'a str],
+    /// Only report what would be installed
+    pub(crate) dry_run: bool,
 }
 
 // Installs or updates a toolchain from a dist server. If an initial
@@ -825,7 +830,7 @@ pub(crate) async fn update_from_dist(
     let fresh_install = !prefix.path().exists();
     if let Some(hash) = opts.update_hash {
         // fresh_install means the toolchain isn't present, but hash_exists means there is a stray hash file
-        if fresh_install && Path::exists(hash) {
+        if fresh_install && !opts.dry_run && Path::exists(hash) {
             (opts.dl_cfg.notify_handler)(Notification::StrayHash(hash));
             std::fs::remove_file(hash)?;
         }
@@ -891,6 +896,7 @@ pub(crate) async fn update_from_dist(
             opts.force,
             opts.components,
             opts.targets,
+            opts.dry_run,
             &mut fetched,
         )
         .await;
@@ -980,10 +986,12 @@ async fn try_update_from_dist_(
     force_update: bool,
     components: &[&str],
     targets: &[&str],
+    dry_run: bool,
     fetched: &mut String,
 ) -> Result<Option<String>> {
     let toolchain_str = toolchain.to_string();
-    let manifestation = Manifestation::open(prefix.clone(), toolchain.target.clone())?;
+    let manifestation =
+        Manifestation::open(prefix.clone(), toolchain.target.clone())?.with_dry_run(dry_run);
 
     // TODO: Add a notification about which manifest version is going to be used
     (download.notify_handler)(Notification::DownloadingManifest(&toolchain_str));
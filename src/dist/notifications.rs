@@ -23,6 +23,7 @@ pub enum Notification<'a> {
     ExtensionNotInstalled(&'a str),
     NonFatalError(&'a anyhow::Error),
     MissingInstalledComponent(&'a str),
+    ComponentStillListed(&'a str),
     DownloadingComponent(&'a str, &'a TargetTriple, Option<&'a TargetTriple>),
     InstallingComponent(&'a str, &'a TargetTriple, Option<&'a TargetTriple>),
     RemovingComponent(&'a str, &'a TargetTriple, Option<&'a TargetTriple>),
@@ -37,6 +38,14 @@ pub enum Notification<'a> {
     StrayHash(&'a Path),
     SignatureInvalid(&'a str),
     RetryingDownload(&'a str),
+    /// A dry-run transaction skipped an operation (e.g. "copy") on a path.
+    DryRun(&'a str, &'a Path),
+    /// A transaction undid an operation (e.g. "add file") on a path.
+    RolledBack(&'a str, &'a Path),
+    /// The number of components installed so far in a batch, and its size.
+    ComponentBatchProgress(usize, usize),
+    /// Another process holds the lock on the installed components.
+    WaitingForComponentsLock(&'a Path),
 }
 
 impl<'a> From<crate::utils::Notification<'a>> for Notification<'a> {
@@ -60,6 +69,7 @@ impl<'a> Notification<'a> {
             ChecksumValid(_)
             | NoUpdateHash(_)
             | FileAlreadyDownloaded
+            | RolledBack(_, _)
             | DownloadingLegacyManifest => NotificationLevel::Debug,
             Extracting(_, _)
             | DownloadingComponent(_, _, _)
@@ -72,10 +82,14 @@ impl<'a> Notification<'a> {
             | DownloadingManifest(_)
             | SkippingNightlyMissingComponent(_, _, _)
             | RetryingDownload(_)
+            | DryRun(_, _)
+            | ComponentBatchProgress(_, _)
+            | WaitingForComponentsLock(_)
             | DownloadedManifest(_, _) => NotificationLevel::Info,
             CantReadUpdateHash(_)
             | ExtensionNotInstalled(_)
             | MissingInstalledComponent(_)
+            | ComponentStillListed(_)
             | CachedFileChecksumFailed
             | ComponentUnavailable(_, _)
             | ForcingUnavailableComponent(_)
@@ -109,6 +123,12 @@ impl<'a> Display for Notification<'a> {
             MissingInstalledComponent(c) => {
                 write!(f, "during uninstall component {c} was not found")
             }
+            ComponentStillListed(c) => {
+                write!(
+                    f,
+                    "component '{c}' is still listed as installed after uninstalling it"
+                )
+            }
             DownloadingComponent(c, h, t) => {
                 if Some(h) == t.as_ref() || t.is_none() {
                     write!(f, "downloading component '{c}'")
@@ -186,6 +206,16 @@ impl<'a> Display for Notification<'a> {
             }
             SignatureInvalid(url) => write!(f, "Signature verification failed for '{url}'"),
             RetryingDownload(url) => write!(f, "retrying download for '{url}'"),
+            DryRun(action, path) => write!(f, "dry run: would {action} '{}'", path.display()),
+            ComponentBatchProgress(done, total) => {
+                write!(f, "installed {done} of {total} components")
+            }
+            RolledBack(kind, path) => write!(f, "rolled back {kind} '{}'", path.display()),
+            WaitingForComponentsLock(path) => write!(
+                f,
+                "waiting for another process to release the lock on '{}'",
+                path.display()
+            ),
         }
     }
 }
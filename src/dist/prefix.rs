@@ -24,6 +24,10 @@ impl InstallPrefix {
         path
     }
 
+    pub(crate) fn rel_manifest_dir(&self) -> PathBuf {
+        PathBuf::from(REL_MANIFEST_DIR)
+    }
+
     pub fn manifest_file(&self, name: &str) -> PathBuf {
         let mut path = self.manifest_dir();
         path.push(name);
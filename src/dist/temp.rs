@@ -3,8 +3,11 @@ use std::fs;
 use std::io;
 use std::ops;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 pub(crate) use anyhow::{Context as _, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use thiserror::Error as ThisError;
 
 use crate::utils::notify::NotificationLevel;
@@ -124,6 +127,8 @@ pub struct Context {
     root_directory: PathBuf,
     pub dist_server: String,
     notify_handler: Box<dyn Fn(Notification<'_>)>,
+    /// Picks the names of new temp files and directories.
+    rng: Mutex<StdRng>,
 }
 
 impl Context {
@@ -136,9 +141,26 @@ impl Context {
             root_directory,
             dist_server: dist_server.to_owned(),
             notify_handler,
+            rng: Mutex::new(StdRng::from_entropy()),
         }
     }
 
+    /// Seeds the generator of temp names, e.g. with [`Process::rng_seed`] so
+    /// that tests get reproducible names.
+    ///
+    /// [`Process::rng_seed`]: crate::currentprocess::Process::rng_seed
+    pub(crate) fn with_rng_seed(self, seed: u64) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            ..self
+        }
+    }
+
+    fn random_name(&self) -> String {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        raw::random_string(16, &mut *rng)
+    }
+
     pub(crate) fn create_root(&self) -> Result<bool> {
         raw::ensure_dir_exists(&self.root_directory, |p| {
             (self.notify_handler)(Notification::CreatingRoot(p));
@@ -150,7 +172,7 @@ impl Context {
         self.create_root()?;
 
         loop {
-            let temp_name = raw::random_string(16) + "_dir";
+            let temp_name = self.random_name() + "_dir";
 
             let temp_dir = self.root_directory.join(temp_name);
 
@@ -176,7 +198,7 @@ impl Context {
         self.create_root()?;
 
         loop {
-            let temp_name = prefix.to_owned() + &raw::random_string(16) + "_file" + ext;
+            let temp_name = prefix.to_owned() + &self.random_name() + "_file" + ext;
 
             let temp_file = self.root_directory.join(temp_name);
 
@@ -207,3 +229,32 @@ impl fmt::Debug for Context {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currentprocess::TestProcess;
+    use crate::dist::DEFAULT_DIST_SERVER;
+
+    #[test]
+    fn seeded_names_are_reproducible() {
+        let names = |seed: &str| {
+            let root = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+            let tp = TestProcess::with_vars(
+                [("RUSTUP_TEST_RNG_SEED".to_owned(), seed.to_owned())].into(),
+            );
+            let cx = Context::new(
+                root.path().to_owned(),
+                DEFAULT_DIST_SERVER,
+                Box::new(|_| ()),
+            )
+            .with_rng_seed(tp.process.rng_seed());
+            let file = cx.new_file().unwrap();
+            let dir = cx.new_directory().unwrap();
+            [&*file, &*dir].map(|p| p.file_name().unwrap().to_owned())
+        };
+
+        assert_eq!(names("42"), names("42"));
+        assert_ne!(names("42"), names("43"));
+    }
+}
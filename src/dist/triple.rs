@@ -1,5 +1,6 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
+use thiserror::Error as ThisError;
 
 pub mod known;
 
@@ -11,6 +12,14 @@ pub struct PartialTargetTriple {
 }
 
 impl PartialTargetTriple {
+    /// Splits a possibly partial target triple into its known architecture,
+    /// OS and environment parts.
+    ///
+    /// The OS part includes the vendor, if any. For bare-metal targets this
+    /// means `none` or `unknown-none` is the OS and what follows is the
+    /// environment, e.g. `riscv32imc-unknown-none-elf` has the OS
+    /// `unknown-none` and the environment `elf`. A lone `none` is taken to
+    /// be an OS, not an environment.
     pub(crate) fn new(name: &str) -> Option<Self> {
         if name.is_empty() {
             return Some(Self {
@@ -52,6 +61,89 @@ impl PartialTargetTriple {
     }
 }
 
+/// The part of a target triple that [`validate_triple`] didn't recognize.
+#[derive(Debug, Clone, PartialEq, Eq, ThisError)]
+pub enum TripleError {
+    #[error("unknown arch '{arch}' in triple '{triple}'")]
+    UnknownArch { arch: String, triple: String },
+    #[error("unknown OS '{os}' in triple '{triple}'")]
+    UnknownOs { os: String, triple: String },
+    #[error("unknown environment '{env}' in triple '{triple}'")]
+    UnknownEnv { env: String, triple: String },
+}
+
+/// Whether every segment of the full target triple `triple` is a known one.
+pub fn is_known_triple(triple: &str) -> bool {
+    validate_triple(triple).is_ok()
+}
+
+/// Checks the architecture, OS and environment of the full target triple
+/// `triple` against the known ones, reporting the first it doesn't know.
+///
+/// Unlike [`PartialTargetTriple::new`], the architecture and the OS are
+/// required. The OS includes the vendor, as in `unknown-linux`.
+pub fn validate_triple(triple: &str) -> Result<(), TripleError> {
+    let (arch, rest) = triple.split_once('-').unwrap_or((triple, ""));
+    if !known::LIST_ARCHS.contains(&arch) {
+        return Err(TripleError::UnknownArch {
+            arch: arch.to_owned(),
+            triple: triple.to_owned(),
+        });
+    }
+
+    // The OS may span several segments, so try the longest one first.
+    let segments = rest.split('-').collect::<Vec<_>>();
+    let mut unknown_env = None;
+    for split in (1..=segments.len()).rev() {
+        let os = segments[..split].join("-");
+        if !known::LIST_OSES.contains(&&*os) {
+            continue;
+        }
+        let env = segments[split..].join("-");
+        if env.is_empty() || known::LIST_ENVS.contains(&&*env) {
+            return Ok(());
+        }
+        unknown_env.get_or_insert(env);
+    }
+
+    if let Some(env) = unknown_env {
+        return Err(TripleError::UnknownEnv {
+            env,
+            triple: triple.to_owned(),
+        });
+    }
+    // Blame the OS alone if the triple ends in a known environment.
+    let os = match segments.split_last() {
+        Some((env, os)) if !os.is_empty() && known::LIST_ENVS.contains(env) => os.join("-"),
+        _ => rest.to_owned(),
+    };
+    Err(TripleError::UnknownOs {
+        os,
+        triple: triple.to_owned(),
+    })
+}
+
+/// Properties of a known target architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchInfo {
+    pub pointer_width: u8,
+    pub big_endian: bool,
+}
+
+impl ArchInfo {
+    /// Looks up an architecture, such as the `arch` of a
+    /// [`PartialTargetTriple`], in the list of known ones.
+    pub fn new(arch: &str) -> Option<Self> {
+        known::ARCH_INFO
+            .iter()
+            .find(|(a, _, _)| *a == arch)
+            .map(|&(_, pointer_width, endian)| Self {
+                pointer_width,
+                big_endian: endian == "big",
+            })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -110,4 +202,118 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_partial_target_triple_bare_metal() {
+        let cases = [
+            (
+                "riscv32imc-unknown-none-elf",
+                ("riscv32imc", "unknown-none", Some("elf")),
+            ),
+            (
+                "thumbv7em-none-eabihf",
+                ("thumbv7em", "none", Some("eabihf")),
+            ),
+            ("thumbv6m-none-eabi", ("thumbv6m", "none", Some("eabi"))),
+            (
+                "aarch64-unknown-none-softfloat",
+                ("aarch64", "unknown-none", Some("softfloat")),
+            ),
+            ("x86_64-unknown-none", ("x86_64", "unknown-none", None)),
+            (
+                "xtensa-esp32-none-elf",
+                ("xtensa", "esp32-none", Some("elf")),
+            ),
+            // Here `none` is the environment of a Linux target.
+            (
+                "x86_64-unknown-linux-none",
+                ("x86_64", "unknown-linux", Some("none")),
+            ),
+        ];
+        for (input, (arch, os, env)) in cases {
+            assert_eq!(
+                PartialTargetTriple::new(input),
+                Some(PartialTargetTriple {
+                    arch: Some(arch.to_owned()),
+                    os: Some(os.to_owned()),
+                    env: env.map(String::from),
+                }),
+                "input: `{input}`"
+            );
+        }
+
+        assert_eq!(
+            PartialTargetTriple::new("none"),
+            Some(PartialTargetTriple {
+                arch: None,
+                os: Some("none".to_owned()),
+                env: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_arch_info() {
+        assert_eq!(
+            ArchInfo::new("x86_64"),
+            Some(ArchInfo {
+                pointer_width: 64,
+                big_endian: false,
+            })
+        );
+        assert_eq!(
+            ArchInfo::new("aarch64_be"),
+            Some(ArchInfo {
+                pointer_width: 64,
+                big_endian: true,
+            })
+        );
+        assert_eq!(ArchInfo::new("unknown-arch"), None);
+
+        for arch in known::LIST_ARCHS {
+            assert!(ArchInfo::new(arch).is_some(), "no info for `{arch}`");
+        }
+    }
+
+    #[test]
+    fn test_validate_triple() {
+        for triple in [
+            "x86_64-unknown-linux-gnu",
+            "aarch64-apple-darwin",
+            "riscv32imc-unknown-none-elf",
+            "x86_64-pc-windows-msvc",
+        ] {
+            assert_eq!(validate_triple(triple), Ok(()), "triple: `{triple}`");
+            assert!(is_known_triple(triple));
+        }
+
+        assert_eq!(
+            validate_triple("x87_64-unknown-linux-gnu"),
+            Err(TripleError::UnknownArch {
+                arch: "x87_64".to_owned(),
+                triple: "x87_64-unknown-linux-gnu".to_owned(),
+            })
+        );
+        assert_eq!(
+            validate_triple("x86_64-unknown-linux-gnux"),
+            Err(TripleError::UnknownEnv {
+                env: "gnux".to_owned(),
+                triple: "x86_64-unknown-linux-gnux".to_owned(),
+            })
+        );
+        assert_eq!(
+            validate_triple("x86_64-unknown-linuz-gnu"),
+            Err(TripleError::UnknownOs {
+                os: "unknown-linuz".to_owned(),
+                triple: "x86_64-unknown-linuz-gnu".to_owned(),
+            })
+        );
+        assert!(!is_known_triple("x86_64"));
+        assert_eq!(
+            validate_triple("x86_64-unknown-linux-gnux")
+                .unwrap_err()
+                .to_string(),
+            "unknown environment 'gnux' in triple 'x86_64-unknown-linux-gnux'"
+        );
+    }
 }
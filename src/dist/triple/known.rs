@@ -166,3 +166,74 @@ pub static LIST_ENVS: &[&str] = &[
     "uclibceabi",
     "uclibceabihf",
 ];
+pub static ARCH_INFO: &[(&str, u8, &str)] = &[
+    ("aarch64", 64, "little"),
+    ("aarch64_be", 64, "big"),
+    ("arm", 32, "little"),
+    ("arm64_32", 32, "little"),
+    ("arm64e", 64, "little"),
+    ("arm64ec", 64, "little"),
+    ("armeb", 32, "big"),
+    ("armebv7r", 32, "big"),
+    ("armv4t", 32, "little"),
+    ("armv5te", 32, "little"),
+    ("armv6", 32, "little"),
+    ("armv6k", 32, "little"),
+    ("armv7", 32, "little"),
+    ("armv7a", 32, "little"),
+    ("armv7k", 32, "little"),
+    ("armv7r", 32, "little"),
+    ("armv7s", 32, "little"),
+    ("armv8r", 32, "little"),
+    ("avr", 16, "little"),
+    ("bpfeb", 64, "big"),
+    ("bpfel", 64, "little"),
+    ("csky", 32, "little"),
+    ("hexagon", 32, "little"),
+    ("i386", 32, "little"),
+    ("i586", 32, "little"),
+    ("i686", 32, "little"),
+    ("loongarch64", 64, "little"),
+    ("m68k", 32, "big"),
+    ("mips", 32, "big"),
+    ("mips64", 64, "big"),
+    ("mips64el", 64, "little"),
+    ("mipsel", 32, "little"),
+    ("mipsisa32r6", 32, "big"),
+    ("mipsisa32r6el", 32, "little"),
+    ("mipsisa64r6", 64, "big"),
+    ("mipsisa64r6el", 64, "little"),
+    ("msp430", 16, "little"),
+    ("nvptx64", 64, "little"),
+    ("powerpc", 32, "big"),
+    ("powerpc64", 64, "big"),
+    ("powerpc64le", 64, "little"),
+    ("riscv32gc", 32, "little"),
+    ("riscv32i", 32, "little"),
+    ("riscv32im", 32, "little"),
+    ("riscv32ima", 32, "little"),
+    ("riscv32imac", 32, "little"),
+    ("riscv32imafc", 32, "little"),
+    ("riscv32imc", 32, "little"),
+    ("riscv64", 64, "little"),
+    ("riscv64gc", 64, "little"),
+    ("riscv64imac", 64, "little"),
+    ("s390x", 64, "big"),
+    ("sparc", 32, "big"),
+    ("sparc64", 64, "big"),
+    ("sparcv9", 64, "big"),
+    ("thumbv4t", 32, "little"),
+    ("thumbv5te", 32, "little"),
+    ("thumbv6m", 32, "little"),
+    ("thumbv7a", 32, "little"),
+    ("thumbv7em", 32, "little"),
+    ("thumbv7m", 32, "little"),
+    ("thumbv7neon", 32, "little"),
+    ("thumbv8m.base", 32, "little"),
+    ("thumbv8m.main", 32, "little"),
+    ("wasm32", 32, "little"),
+    ("wasm64", 64, "little"),
+    ("x86_64", 64, "little"),
+    ("x86_64h", 64, "little"),
+    ("xtensa", 32, "little"),
+];
@@ -3,6 +3,8 @@ use std::env;
 use std::path::PathBuf;
 use std::process::Command;
 
+use anyhow::{bail, Result};
+
 use crate::currentprocess::Process;
 
 pub const RUST_RECURSION_COUNT_MAX: u32 = 20;
@@ -31,14 +33,48 @@ pub(crate) fn prepend_path(
     }
 }
 
-pub(crate) fn inc(name: &str, cmd: &mut Command, process: &Process) {
-    let old_value = process
-        .var(name)
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(0);
+/// The nesting depth of rustup proxies, tracked across processes through the
+/// `RUST_RECURSION_COUNT` environment variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecursionGuard {
+    count: u32,
+}
+
+impl RecursionGuard {
+    pub const VAR: &'static str = "RUST_RECURSION_COUNT";
 
-    cmd.env(name, (old_value + 1).to_string());
+    /// Reads the count from the environment. A missing or malformed value
+    /// counts as 0.
+    pub fn from_process(process: &Process) -> Self {
+        let count = process
+            .var(Self::VAR)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        Self { count }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Whether the count is above `max`.
+    pub fn exceeds(&self, max: u32) -> bool {
+        self.count > max
+    }
+
+    /// Fails if the count is above [`RUST_RECURSION_COUNT_MAX`].
+    pub fn check(&self) -> Result<()> {
+        if self.exceeds(RUST_RECURSION_COUNT_MAX) {
+            bail!("infinite recursion detected");
+        }
+        Ok(())
+    }
+
+    /// Exports the incremented count to a child process.
+    pub(crate) fn set_for_child(&self, cmd: &mut Command) {
+        cmd.env(Self::VAR, self.count.saturating_add(1).to_string());
+    }
 }
 
 #[cfg(test)]
@@ -99,4 +135,55 @@ mod tests {
             ),]
         );
     }
+
+    #[test]
+    fn recursion_guard_missing_var() {
+        let tp = TestProcess::with_vars(HashMap::new());
+        let guard = RecursionGuard::from_process(&tp.process);
+        assert_eq!(guard.count(), 0);
+        assert!(guard.check().is_ok());
+
+        let mut cmd = Command::new("test");
+        guard.set_for_child(&mut cmd);
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            &[(OsStr::new(RecursionGuard::VAR), Some(OsStr::new("1")))]
+        );
+    }
+
+    #[test]
+    fn recursion_guard_valid_count() {
+        let mut vars = HashMap::new();
+        vars.env(RecursionGuard::VAR, "7");
+        let tp = TestProcess::with_vars(vars);
+        let guard = RecursionGuard::from_process(&tp.process);
+        assert_eq!(guard.count(), 7);
+        assert!(guard.check().is_ok());
+    }
+
+    #[test]
+    fn recursion_guard_overflow() {
+        let mut vars = HashMap::new();
+        vars.env(
+            RecursionGuard::VAR,
+            (RUST_RECURSION_COUNT_MAX + 1).to_string(),
+        );
+        let tp = TestProcess::with_vars(vars);
+        let guard = RecursionGuard::from_process(&tp.process);
+        assert!(guard.exceeds(RUST_RECURSION_COUNT_MAX));
+        assert!(guard.check().is_err());
+
+        let mut vars = HashMap::new();
+        vars.env(RecursionGuard::VAR, u32::MAX.to_string());
+        let tp = TestProcess::with_vars(vars);
+        let mut cmd = Command::new("test");
+        RecursionGuard::from_process(&tp.process).set_for_child(&mut cmd);
+        let envs: Vec<_> = cmd.get_envs().collect();
+        let expected = u32::MAX.to_string();
+        assert_eq!(
+            envs,
+            &[(OsStr::new(RecursionGuard::VAR), Some(OsStr::new(&expected)))]
+        );
+    }
 }
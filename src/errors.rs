@@ -35,6 +35,18 @@ pub enum RustupError {
     ComponentMissingFile { name: String, path: PathBuf },
     #[error("could not create {name} directory: '{}'", .path.display())]
     CreatingDirectory { name: &'static str, path: PathBuf },
+    #[error("install prefix does not exist or is not a directory: '{}'", .0.display())]
+    InstallPrefixNotFound(PathBuf),
+    #[error("not running under a rustup toolchain: RUSTUP_TOOLCHAIN is not set")]
+    NoCurrentToolchain,
+    #[error("install prefix is not writable: '{}'", .path.display())]
+    PrefixNotWritable { path: PathBuf },
+    #[error("installation at '{}' was opened read-only", .path.display())]
+    ComponentsReadOnly { path: PathBuf },
+    #[error("invalid component name: {0:?}")]
+    InvalidComponentName(String),
+    #[error("component name {0:?} contains a path separator and cannot name a manifest file")]
+    ComponentManifestNameHasSeparator(String),
     #[error("invalid toolchain name: '{0}'")]
     InvalidToolchainName(String),
     #[error("could not create link from '{}' to '{}'", .src.display(), .dest.display())]
@@ -44,6 +56,8 @@ pub enum RustupError {
     #[cfg(not(windows))]
     #[error("failed to set permissions for '{}'", .p.display())]
     SettingPermissions { p: PathBuf, source: io::Error },
+    #[error("operation cancelled")]
+    Cancelled,
     #[error("checksum failed for '{url}', expected: '{expected}', calculated: '{calculated}'")]
     ChecksumFailed {
         url: String,
@@ -52,6 +66,14 @@ pub enum RustupError {
     },
     #[error("failed to install component: '{name}', detected conflict: '{}'", .path.display())]
     ComponentConflict { name: String, path: PathBuf },
+    #[error("component '{name}' has no part '{}'", .path.display())]
+    ComponentPartNotFound { name: String, path: PathBuf },
+    #[error("failed to install component: '{name}', '{}' is owned by component '{owner}'", .path.display())]
+    ComponentPathOwned {
+        name: String,
+        owner: String,
+        path: PathBuf,
+    },
     #[error("toolchain '{0}' does not support components")]
     ComponentsUnsupported(String),
     #[error("toolchain '{0}' does not support components (v1 manifest)")]
@@ -63,7 +63,9 @@ impl<'a> InstallMethod<'a> {
                 UpdateStatus::Unchanged
             }
             true => {
-                (nh)(RootNotification::InstalledToolchain(&self.dest_basename()));
+                if !self.dry_run() {
+                    (nh)(RootNotification::InstalledToolchain(&self.dest_basename()));
+                }
                 match self {
                     InstallMethod::Dist(DistOptions {
                         old_date_version: Some((_, v)),
@@ -76,6 +78,11 @@ impl<'a> InstallMethod<'a> {
             }
         };
 
+        // A dry run leaves nothing behind to check
+        if self.dry_run() {
+            return Ok(status);
+        }
+
         // Final check, to ensure we're installed
         match Toolchain::exists(self.cfg(), &self.local_name())? {
             true => Ok(status),
@@ -108,7 +115,7 @@ impl<'a> InstallMethod<'a> {
                 let maybe_new_hash = dist::update_from_dist(prefix, opts).await?;
 
                 if let Some(hash) = maybe_new_hash {
-                    if let Some(hash_file) = opts.update_hash {
+                    if let Some(hash_file) = opts.update_hash.filter(|_| !opts.dry_run) {
                         utils::write_file("update hash", hash_file, &hash)?;
                     }
 
@@ -128,6 +135,10 @@ impl<'a> InstallMethod<'a> {
         }
     }
 
+    fn dry_run(&self) -> bool {
+        matches!(self, InstallMethod::Dist(DistOptions { dry_run: true, .. }))
+    }
+
     fn local_name(&self) -> LocalToolchainName {
         match self {
             InstallMethod::Copy { dest, .. } => (*dest).into(),
@@ -211,6 +211,29 @@ impl fmt::Display for RustupHome {
     }
 }
 
+/// Removes the ANSI escape sequences, such as the colors and bold text of the
+/// log level prefixes, from captured terminal output.
+pub fn strip_ansi(bytes: &[u8]) -> String {
+    let mut plain = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter().copied();
+    while let Some(b) = bytes.next() {
+        if b != 0x1b {
+            plain.push(b);
+            continue;
+        }
+        // A control sequence runs from `ESC [` up to its final byte, which is
+        // in `@..=~`; other escapes are a single byte after the `ESC`.
+        if bytes.next() == Some(b'[') {
+            for b in bytes.by_ref() {
+                if (0x40..=0x7e).contains(&b) {
+                    break;
+                }
+            }
+        }
+    }
+    String::from_utf8_lossy(&plain).into_owned()
+}
+
 /// Create an isolated rustup home with no content, then call f with it, and
 /// delete it afterwards.
 #[cfg(test)]
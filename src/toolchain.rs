@@ -53,12 +53,18 @@ impl<'a> Toolchain<'a> {
             Err(RustupError::ToolchainNotInstalled(ToolchainName::Official(desc)))
                 if install_if_missing =>
             {
-                Ok(
-                    DistributableToolchain::install(cfg, &desc, &[], &[], cfg.get_profile()?, true)
-                        .await?
-                        .1
-                        .toolchain,
+                Ok(DistributableToolchain::install(
+                    cfg,
+                    &desc,
+                    &[],
+                    &[],
+                    cfg.get_profile()?,
+                    true,
+                    false,
                 )
+                .await?
+                .1
+                .toolchain)
             }
             Err(e) => Err(e.into()),
         }
@@ -167,7 +173,7 @@ impl<'a> Toolchain<'a> {
             cmd.env("CARGO_HOME", &cargo_home);
         }
 
-        env_var::inc("RUST_RECURSION_COUNT", cmd, self.cfg.process);
+        env_var::RecursionGuard::from_process(self.cfg.process).set_for_child(cmd);
 
         cmd.env("RUSTUP_TOOLCHAIN", format!("{}", self.name));
         cmd.env("RUSTUP_HOME", &self.cfg.rustup_dir);
@@ -366,14 +372,8 @@ impl<'a> Toolchain<'a> {
         let path = if utils::is_file(&bin_path) {
             &bin_path
         } else {
-            let recursion_count = self
-                .cfg
-                .process
-                .var("RUST_RECURSION_COUNT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0);
-            if recursion_count > env_var::RUST_RECURSION_COUNT_MAX - 1 {
+            let guard = env_var::RecursionGuard::from_process(self.cfg.process);
+            if guard.exceeds(env_var::RUST_RECURSION_COUNT_MAX - 1) {
                 let binary_lossy: String = binary.to_string_lossy().into();
                 if matches!(
                     &self.name,
@@ -332,8 +332,9 @@ impl<'a> DistributableToolchain<'a> {
         targets: &[&str],
         profile: Profile,
         force: bool,
+        dry_run: bool,
     ) -> anyhow::Result<(UpdateStatus, DistributableToolchain<'a>)> {
-        let hash_path = cfg.get_hash_file(toolchain, true)?;
+        let hash_path = cfg.get_hash_file(toolchain, !dry_run)?;
         let update_hash = Some(&hash_path as &Path);
 
         let status = InstallMethod::Dist(DistOptions {
@@ -348,6 +349,7 @@ impl<'a> DistributableToolchain<'a> {
             old_date_version: None,
             components,
             targets,
+            dry_run,
         })
         .install()
         .await?;
@@ -365,7 +367,7 @@ impl<'a> DistributableToolchain<'a> {
             Ok(UpdateStatus::Unchanged)
         } else {
             Ok(
-                Self::install(cfg, desc, &[], &[], cfg.get_profile()?, false)
+                Self::install(cfg, desc, &[], &[], cfg.get_profile()?, false, false)
                     .await?
                     .0,
             )
@@ -379,7 +381,7 @@ impl<'a> DistributableToolchain<'a> {
         targets: &[&str],
         profile: Profile,
     ) -> anyhow::Result<UpdateStatus> {
-        self.update_extra(components, targets, profile, true, false)
+        self.update_extra(components, targets, profile, true, false, false)
             .await
     }
 
@@ -392,6 +394,7 @@ impl<'a> DistributableToolchain<'a> {
         profile: Profile,
         force: bool,
         allow_downgrade: bool,
+        dry_run: bool,
     ) -> anyhow::Result<UpdateStatus> {
         let old_date_version =
             // Ignore a missing manifest: we can't report the old version
@@ -409,7 +412,7 @@ impl<'a> DistributableToolchain<'a> {
                 .ok();
 
         let cfg = self.toolchain.cfg;
-        let hash_path = cfg.get_hash_file(&self.desc, true)?;
+        let hash_path = cfg.get_hash_file(&self.desc, !dry_run)?;
         let update_hash = Some(&hash_path as &Path);
 
         InstallMethod::Dist(DistOptions {
@@ -424,6 +427,7 @@ impl<'a> DistributableToolchain<'a> {
             old_date_version,
             components,
             targets,
+            dry_run,
         })
         .install()
         .await
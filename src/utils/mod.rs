@@ -6,4 +6,4 @@ pub(crate) mod units;
 pub mod utils;
 
 pub(crate) use crate::utils::notifications::Notification;
-pub(crate) mod notify;
+pub mod notify;
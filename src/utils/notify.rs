@@ -2,8 +2,9 @@ use std::fmt;
 
 use tracing::Level;
 
-#[derive(Debug)]
-pub(crate) enum NotificationLevel {
+/// The severity of a log line, ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NotificationLevel {
     Trace,
     Debug,
     Info,
@@ -11,15 +12,34 @@ pub(crate) enum NotificationLevel {
     Error,
 }
 
+impl NotificationLevel {
+    /// Whether this level is as severe as `threshold` or more, e.g. to only
+    /// act on warnings and errors.
+    pub(crate) fn at_least(self, threshold: Self) -> bool {
+        self >= threshold
+    }
+
+    /// The prefix to print in front of a log line of this level: either the
+    /// level's name, or a single-character icon.
+    pub(crate) fn prefix(&self, icons: bool) -> &'static str {
+        match (self, icons) {
+            (NotificationLevel::Trace, false) => "trace",
+            (NotificationLevel::Debug, false) => "debug",
+            (NotificationLevel::Info, false) => "info",
+            (NotificationLevel::Warn, false) => "warn",
+            (NotificationLevel::Error, false) => "error",
+            (NotificationLevel::Trace, true) => "\u{b7}",
+            (NotificationLevel::Debug, true) => "\u{203a}",
+            (NotificationLevel::Info, true) => "\u{2713}",
+            (NotificationLevel::Warn, true) => "!",
+            (NotificationLevel::Error, true) => "\u{2717}",
+        }
+    }
+}
+
 impl fmt::Display for NotificationLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(match self {
-            NotificationLevel::Trace => "trace",
-            NotificationLevel::Debug => "debug",
-            NotificationLevel::Info => "info",
-            NotificationLevel::Warn => "warn",
-            NotificationLevel::Error => "error",
-        })
+        f.write_str(self.prefix(false))
     }
 }
 
@@ -34,3 +54,35 @@ impl From<Level> for NotificationLevel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix() {
+        let cases = [
+            (NotificationLevel::Trace, "trace", "\u{b7}"),
+            (NotificationLevel::Debug, "debug", "\u{203a}"),
+            (NotificationLevel::Info, "info", "\u{2713}"),
+            (NotificationLevel::Warn, "warn", "!"),
+            (NotificationLevel::Error, "error", "\u{2717}"),
+        ];
+        for (level, word, icon) in cases {
+            assert_eq!(level.prefix(false), word);
+            assert_eq!(level.to_string(), word);
+            assert_eq!(level.prefix(true), icon);
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        use NotificationLevel::*;
+        let levels = [Trace, Debug, Info, Warn, Error];
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+        assert!(Error >= Warn);
+        assert!(Error.at_least(Warn));
+        assert!(Warn.at_least(Warn));
+        assert!(!Info.at_least(Warn));
+    }
+}
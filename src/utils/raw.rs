@@ -56,10 +56,8 @@ pub fn path_exists<P: AsRef<Path>>(path: P) -> bool {
     fs::metadata(path).is_ok()
 }
 
-pub(crate) fn random_string(length: usize) -> String {
-    use rand::Rng;
+pub(crate) fn random_string(length: usize, rng: &mut impl rand::Rng) -> String {
     const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_";
-    let mut rng = rand::thread_rng();
     (0..length)
         .map(|_| char::from(CHARSET[rng.gen_range(0..CHARSET.len())]))
         .collect()
@@ -234,6 +232,64 @@ fn symlink_junction_inner(target: &Path, junction: &Path) -> io::Result<()> {
     }
 }
 
+/// Takes an exclusive advisory lock on `file`, which is released when the
+/// file is closed. Unless `wait` is set, returns `false` right away if
+/// another handle holds the lock.
+#[cfg(not(windows))]
+pub(crate) fn lock_exclusive(file: &File, wait: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let flags = if wait {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_EX | libc::LOCK_NB
+    };
+    if unsafe { libc::flock(file.as_raw_fd(), flags) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn lock_exclusive(file: &File, wait: bool) -> io::Result<bool> {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::Foundation::{ERROR_LOCK_VIOLATION, HANDLE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    };
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+
+    let flags = if wait {
+        LOCKFILE_EXCLUSIVE_LOCK
+    } else {
+        LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY
+    };
+    let res = unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        LockFileEx(
+            file.as_raw_handle() as HANDLE,
+            flags,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if res != 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(code) if code == ERROR_LOCK_VIOLATION as i32 => Ok(false),
+        _ => Err(err),
+    }
+}
+
 pub(crate) fn hardlink(src: &Path, dest: &Path) -> io::Result<()> {
     let _ = fs::remove_file(dest);
     fs::hard_link(src, dest)
@@ -274,8 +330,9 @@ pub(crate) fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
 #[cfg(not(windows))]
 fn has_cmd(cmd: &str, process: &Process) -> bool {
     let cmd = format!("{}{}", cmd, env::consts::EXE_SUFFIX);
-    let path = process.var_os("PATH").unwrap_or_default();
-    env::split_paths(&path)
+    process
+        .path_entries()
+        .into_iter()
         .map(|p| p.join(&cmd))
         .any(|p| p.exists())
 }
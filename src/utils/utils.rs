@@ -3,6 +3,7 @@ use std::fs::{self, File};
 use std::io::{self, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
 use retry::delay::{jitter, Fibonacci};
@@ -311,7 +312,7 @@ pub fn hardlink_file(src: &Path, dest: &Path) -> Result<()> {
 }
 
 #[cfg(unix)]
-fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
+pub(crate) fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
     std::os::unix::fs::symlink(src, dest).with_context(|| RustupError::LinkingFile {
         src: PathBuf::from(src),
         dest: PathBuf::from(dest),
@@ -319,7 +320,7 @@ fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
 }
 
 #[cfg(windows)]
-fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
+pub(crate) fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
     // we are supposed to not use symlink on windows
     Err(anyhow!(RustupError::LinkingFile {
         src: PathBuf::from(src),
@@ -429,7 +430,7 @@ pub(crate) fn open_browser(path: &Path) -> Result<()> {
 }
 
 #[cfg(not(windows))]
-fn set_permissions(path: &Path, perms: fs::Permissions) -> Result<()> {
+pub(crate) fn set_permissions(path: &Path, perms: fs::Permissions) -> Result<()> {
     fs::set_permissions(path, perms).map_err(|e| {
         RustupError::SettingPermissions {
             p: PathBuf::from(path),
@@ -565,6 +566,67 @@ where
     })
 }
 
+/// How the file system operations of a transaction are retried when they
+/// fail with a transient error, e.g. on a file that another process has
+/// briefly locked, as is common on network file systems.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FsRetry {
+    /// How many times a failing operation is retried.
+    pub(crate) retries: u32,
+    /// The delay before the first retry, doubled for each further one.
+    pub(crate) base_delay: Duration,
+}
+
+impl FsRetry {
+    /// Reads `RUSTUP_FS_RETRIES` and `RUSTUP_FS_RETRY_DELAY_MS`, defaulting
+    /// to 3 retries after 50ms.
+    pub(crate) fn from_process(process: &Process) -> Self {
+        let var = |key| process.var(key).ok().and_then(|v| v.trim().parse().ok());
+        Self {
+            retries: var("RUSTUP_FS_RETRIES").unwrap_or(3),
+            base_delay: Duration::from_millis(var("RUSTUP_FS_RETRY_DELAY_MS").unwrap_or(50).into()),
+        }
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that isn't
+    /// transient, or has been retried `retries` times, returning its last
+    /// result.
+    pub(crate) fn run<T>(&self, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut delay = self.base_delay;
+        for _ in 0..self.retries {
+            match op() {
+                Err(e) if is_transient(&e) => {
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+        op()
+    }
+}
+
+fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|e| e.downcast_ref::<io::Error>())
+        .any(|e| {
+            if matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) {
+                return true;
+            }
+            #[cfg(unix)]
+            let codes = [libc::EBUSY, libc::ETXTBSY];
+            #[cfg(windows)]
+            let codes = [
+                windows_sys::Win32::Foundation::ERROR_SHARING_VIOLATION as i32,
+                windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION as i32,
+            ];
+            e.raw_os_error().is_some_and(|code| codes.contains(&code))
+        })
+}
+
 pub(crate) fn delete_dir_contents_following_links(dir_path: &Path) {
     use remove_dir_all::RemoveDir;
 
@@ -666,7 +728,10 @@ pub(crate) fn home_dir_from_passwd() -> Option<PathBuf> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
+    use crate::currentprocess::TestProcess;
 
     #[test]
     fn test_remove_file() {
@@ -702,4 +767,67 @@ mod tests {
         assert!(!f_path.exists());
         assert!(ensure_file_removed("f", &f_path).is_ok());
     }
+
+    #[test]
+    fn fs_retry_retries_transient_errors() {
+        let retry = FsRetry {
+            retries: 2,
+            base_delay: Duration::ZERO,
+        };
+        let fail = |kind: io::ErrorKind| Err(io::Error::from(kind)).context("could not rename");
+
+        // Fails once with a transient error, then succeeds.
+        let mut calls = 0;
+        let result = retry.run(|| {
+            calls += 1;
+            match calls {
+                1 => fail(io::ErrorKind::Interrupted),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        // Gives up after the configured retries with the last error.
+        let mut calls = 0;
+        let err = retry
+            .run(|| -> Result<()> {
+                calls += 1;
+                fail(io::ErrorKind::TimedOut)
+            })
+            .unwrap_err();
+        assert_eq!(calls, 3);
+        assert_eq!(
+            err.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::TimedOut
+        );
+
+        // Other errors aren't retried.
+        let mut calls = 0;
+        retry
+            .run(|| -> Result<()> {
+                calls += 1;
+                fail(io::ErrorKind::NotFound)
+            })
+            .unwrap_err();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn fs_retry_from_process() {
+        let tp = TestProcess::with_vars(HashMap::from([
+            ("RUSTUP_FS_RETRIES".to_owned(), "5".to_owned()),
+            ("RUSTUP_FS_RETRY_DELAY_MS".to_owned(), "10".to_owned()),
+        ]));
+        assert_eq!(
+            FsRetry::from_process(&tp.process),
+            FsRetry {
+                retries: 5,
+                base_delay: Duration::from_millis(10),
+            }
+        );
+        assert_eq!(
+            FsRetry::from_process(&TestProcess::default().process).retries,
+            3
+        );
+    }
 }
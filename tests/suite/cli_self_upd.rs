@@ -116,6 +116,24 @@ async fn install_twice() {
     assert!(rustup.exists());
 }
 
+#[tokio::test]
+async fn install_dry_run() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    #[cfg(windows)]
+    let _path_guard = RegistryGuard::new(&USER_PATH).unwrap();
+
+    let out = cx.config.run("rustup-init", ["-y", "--dry-run"], &[]).await;
+    assert!(out.ok);
+    assert!(out.stdout.contains("Current installation options"));
+    assert!(out.stderr.contains("info: dry run: would copy 'bin/rustc'"));
+    assert!(out
+        .stderr
+        .contains("info: dry run: no changes have been made"));
+    let rustup = cx.config.cargodir.join(format!("bin/rustup{EXE_SUFFIX}"));
+    assert!(!rustup.exists());
+    assert!(!cx.config.rustupdir.has("toolchains"));
+}
+
 /// Smoke test for the entire install process when dirs need to be made :
 /// depending just on unit tests here could miss subtle dependencies being added
 /// earlier in the code, so a black-box test is needed.
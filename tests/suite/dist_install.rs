@@ -1,14 +1,16 @@
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::path::PathBuf;
 
 use rustup::currentprocess::TestProcess;
 use rustup::dist::component::Components;
 use rustup::dist::component::Transaction;
-use rustup::dist::component::{DirectoryPackage, Package};
+use rustup::dist::component::{DirectoryPackage, Extract, ExtractPackage, Package};
 use rustup::dist::prefix::InstallPrefix;
 use rustup::dist::temp;
 use rustup::dist::Notification;
 use rustup::dist::DEFAULT_DIST_SERVER;
+use rustup::utils::notify::NotificationLevel;
 use rustup::utils::utils;
 
 use rustup::test::mock::{MockComponentBuilder, MockFile, MockInstallerBuilder};
@@ -124,7 +126,7 @@ fn basic_install() {
 
     let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
 
-    let tx = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
     tx.commit();
 
     assert!(utils::path_exists(instdir.path().join("bin/foo")));
@@ -135,6 +137,100 @@ fn basic_install() {
     assert!(components.find("mycomponent").unwrap().is_some());
 }
 
+#[test]
+fn hardlinked_component_install() {
+    let pkgdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let mock = MockInstallerBuilder {
+        components: vec![MockComponentBuilder {
+            name: "mycomponent".to_string(),
+            files: vec![MockFile::new("bin/foo", b"foo")],
+        }],
+    };
+
+    mock.build(pkgdir.path());
+
+    let instdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(instdir.path().to_owned());
+
+    let tmpdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let tmp_cx = temp::Context::new(
+        tmpdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+
+    let components = Components::open(prefix).unwrap();
+
+    let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true)
+        .unwrap()
+        .with_hardlinks(true);
+
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    tx.commit();
+
+    assert!(same_file::is_same_file(
+        pkgdir.path().join("mycomponent/bin/foo"),
+        instdir.path().join("bin/foo"),
+    )
+    .unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn symlink_component_install() {
+    let pkgdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let mock = MockInstallerBuilder {
+        components: vec![MockComponentBuilder {
+            name: "mycomponent".to_string(),
+            files: vec![MockFile::new("lib/foo", b"foo")],
+        }],
+    };
+
+    mock.build(pkgdir.path());
+    let component_dir = pkgdir.path().join("mycomponent");
+    std::fs::create_dir_all(component_dir.join("bin")).unwrap();
+    std::os::unix::fs::symlink("../lib/foo", component_dir.join("bin/foo")).unwrap();
+    let mut manifest = std::fs::OpenOptions::new()
+        .append(true)
+        .open(component_dir.join("manifest.in"))
+        .unwrap();
+    writeln!(manifest, "symlink:bin/foo").unwrap();
+
+    let instdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(instdir.path().to_owned());
+
+    let tmpdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let tmp_cx = temp::Context::new(
+        tmpdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+
+    let components = Components::open(prefix).unwrap();
+
+    let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
+
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    tx.commit();
+
+    assert_eq!(
+        std::fs::read_link(instdir.path().join("bin/foo")).unwrap(),
+        PathBuf::from("../lib/foo")
+    );
+    assert_eq!(
+        std::fs::read_to_string(instdir.path().join("bin/foo")).unwrap(),
+        "foo"
+    );
+}
+
 #[test]
 fn multiple_component_install() {
     let pkgdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
@@ -171,8 +267,8 @@ fn multiple_component_install() {
 
     let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
 
-    let tx = pkg.install(&components, "mycomponent", None, tx).unwrap();
-    let tx = pkg.install(&components, "mycomponent2", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent2", None, tx).unwrap();
     tx.commit();
 
     assert!(utils::path_exists(instdir.path().join("bin/foo")));
@@ -222,8 +318,8 @@ fn uninstall() {
 
     let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
 
-    let tx = pkg.install(&components, "mycomponent", None, tx).unwrap();
-    let tx = pkg.install(&components, "mycomponent2", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent2", None, tx).unwrap();
     tx.commit();
 
     // Now uninstall
@@ -231,7 +327,7 @@ fn uninstall() {
     let tp = TestProcess::default();
     let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
     for component in components.list().unwrap() {
-        tx = component.uninstall(tx, &tp.process).unwrap();
+        tx = component.uninstall(tx, &tp.process, None).unwrap();
     }
     tx.commit();
 
@@ -281,7 +377,7 @@ fn component_bad_version() {
 
     let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
 
-    let tx = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
     tx.commit();
 
     // Write a bogus version to the component manifest directory
@@ -328,8 +424,100 @@ fn install_to_prefix_that_does_not_exist() {
 
     let pkg = DirectoryPackage::new(pkgdir.path().to_owned(), true).unwrap();
 
-    let tx = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    let (tx, _) = pkg.install(&components, "mycomponent", None, tx).unwrap();
     tx.commit();
 
     assert!(utils::path_exists(does_not_exist.join("bin/foo")));
 }
+
+/// A "package" whose files are already in memory, standing in for one in an
+/// archive format that rustup doesn't handle itself.
+#[derive(Debug)]
+struct InMemoryPackage(Vec<(&'static str, &'static str, &'static [u8])>);
+
+impl Extract for InMemoryPackage {
+    fn components(&self) -> Vec<String> {
+        let mut names = self
+            .0
+            .iter()
+            .map(|(c, _, _)| c.to_string())
+            .collect::<Vec<_>>();
+        names.dedup();
+        names
+    }
+
+    fn extract(
+        &self,
+        component: &str,
+        add: &mut dyn FnMut(PathBuf, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        for (_, path, contents) in self.0.iter().filter(|(c, _, _)| *c == component) {
+            add(PathBuf::from(*path), &mut &contents[..])?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn extract_package_install() {
+    let instdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(instdir.path().to_owned());
+
+    let tmpdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let tmp_cx = temp::Context::new(
+        tmpdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let notify = |_: Notification<'_>| ();
+    let tp = TestProcess::default();
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+
+    let components = Components::open(prefix).unwrap();
+
+    let pkg = ExtractPackage(InMemoryPackage(vec![
+        ("mycomponent", "bin/foo", b"foo".as_slice()),
+        ("mycomponent", "lib/bar", b"bar!".as_slice()),
+    ]));
+    assert!(pkg.contains("mycomponent", None));
+    assert!(!pkg.contains("other", None));
+
+    let (tx, summary) = pkg.install(&components, "mycomponent", None, tx).unwrap();
+    tx.commit();
+
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.bytes, 7);
+    assert_eq!(
+        std::fs::read(instdir.path().join("bin/foo")).unwrap(),
+        b"foo"
+    );
+    assert_eq!(
+        std::fs::read(instdir.path().join("lib/bar")).unwrap(),
+        b"bar!"
+    );
+    assert!(components.find("mycomponent").unwrap().is_some());
+}
+
+#[test]
+fn audit_logs_corrupt_manifests() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let components_file = prefix.manifest_file("components");
+    std::fs::create_dir_all(components_file.parent().unwrap()).unwrap();
+    utils::write_file("", &components_file, "mycomponent\n").unwrap();
+    utils::write_file(
+        "",
+        &prefix.manifest_file("manifest-mycomponent"),
+        "socket:bin/foo\n",
+    )
+    .unwrap();
+
+    let tp = TestProcess::default();
+    let components = Components::open(prefix).unwrap();
+    let report = components.audit().unwrap();
+    assert_eq!(report.corrupt_manifests, ["mycomponent"]);
+    assert!(tp.captured_logs().contains(&(
+        NotificationLevel::Warn,
+        "manifest of component 'mycomponent' is corrupt".to_owned()
+    )));
+}
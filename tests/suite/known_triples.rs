@@ -1,4 +1,7 @@
-use std::{collections::BTreeSet, io::Write};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
 
 use platforms::Platform;
 
@@ -8,7 +11,15 @@ fn gen_known_triples() {
     let existing = std::fs::read_to_string(out_path).unwrap();
 
     let (mut archs, mut oses, mut envs) = (BTreeSet::new(), BTreeSet::new(), BTreeSet::new());
-    for (arch, os, env) in Platform::ALL.iter().map(|p| parse_triple(p.target_triple)) {
+    // Counts how often each (pointer width, endianness) pair is seen per arch.
+    let mut arch_info = BTreeMap::<_, BTreeMap<_, usize>>::new();
+    for p in Platform::ALL {
+        let (arch, os, env) = parse_triple(p.target_triple);
+        *arch_info
+            .entry(arch)
+            .or_default()
+            .entry((p.target_pointer_width.as_str(), p.target_endian.as_str()))
+            .or_default() += 1;
         archs.insert(arch);
         oses.insert(os);
         if !env.is_empty() {
@@ -16,6 +27,13 @@ fn gen_known_triples() {
         }
     }
 
+    for (name, list) in [("archs", &archs), ("oses", &oses), ("envs", &envs)] {
+        assert!(
+            !list.is_empty(),
+            "no known {name} found in `platforms`, refusing to generate `{out_path}`"
+        );
+    }
+
     let expected = {
         let mut buf = String::new();
 
@@ -37,6 +55,15 @@ fn gen_known_triples() {
         }
         buf.push_str("];\n");
 
+        // A few archs have variants with a different data model (e.g. `x86_64`
+        // with the x32 ABI), so record the most common properties per arch.
+        buf.push_str("pub static ARCH_INFO: &[(&str, u8, &str)] = &[\n");
+        for (arch, seen) in arch_info {
+            let ((width, endian), _) = seen.into_iter().max_by_key(|(_, n)| *n).unwrap();
+            buf.push_str(&format!("    (\"{arch}\", {width}, \"{endian}\"),\n"));
+        }
+        buf.push_str("];\n");
+
         buf
     };
 
@@ -50,6 +77,24 @@ fn gen_known_triples() {
     }
 }
 
+#[test]
+fn known_triples_are_valid() {
+    for p in Platform::ALL {
+        assert!(
+            rustup::dist::is_known_triple(p.target_triple),
+            "`{}` is not a known triple",
+            p.target_triple
+        );
+    }
+    assert_eq!(
+        rustup::dist::validate_triple("x86_64-unknown-linux-foo"),
+        Err(rustup::dist::TripleError::UnknownEnv {
+            env: "foo".to_owned(),
+            triple: "x86_64-unknown-linux-foo".to_owned(),
+        })
+    );
+}
+
 /// Parses the given triple into 3 parts (target architecture, OS and environment).
 ///
 /// # Discussion